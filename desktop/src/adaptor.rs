@@ -0,0 +1,52 @@
+use tari_crypto::{
+    keys::PublicKey,
+    ristretto::{RistrettoPublicKey, RistrettoSchnorr, RistrettoSecretKey},
+    tari_utilities::ByteArray,
+};
+
+use crate::{DomainSeparatedConsensusHasher, TransactionHashDomain};
+
+/// Compute the challenge the device uses for an adaptor signature. The challenge commits to the adapted nonce `R + T`
+/// rather than the public nonce `R` returned with the pre-signature.
+pub fn adaptor_challenge(
+    public_key: &RistrettoPublicKey,
+    public_nonce: &RistrettoPublicKey,
+    adaptor_point: &RistrettoPublicKey,
+    challenge: &[u8; 32],
+) -> RistrettoSecretKey {
+    let adapted_nonce = public_nonce + adaptor_point;
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("script_challenge")
+        .chain(public_key)
+        .chain(&adapted_nonce)
+        .chain(challenge)
+        .finalize();
+    RistrettoSecretKey::from_bytes(&hash).unwrap()
+}
+
+/// Verify a pre-signature `(R, s')` returned by `SignAdaptor`, i.e. check that `s'.G = R + e.P`.
+pub fn verify_adaptor_signature(
+    public_key: &RistrettoPublicKey,
+    pre_signature: &RistrettoSchnorr,
+    adaptor_point: &RistrettoPublicKey,
+    challenge: &[u8; 32],
+) -> bool {
+    let e = adaptor_challenge(public_key, pre_signature.get_public_nonce(), adaptor_point, challenge);
+    pre_signature.verify(public_key, &e)
+}
+
+/// Complete a pre-signature with the adaptor secret `t`, giving the ordinary signature `(R + T, s' + t)`.
+pub fn complete_adaptor_signature(
+    pre_signature: &RistrettoSchnorr,
+    adaptor_secret: &RistrettoSecretKey,
+) -> RistrettoSchnorr {
+    let adaptor_point = RistrettoPublicKey::from_secret_key(adaptor_secret);
+    RistrettoSchnorr::new(
+        pre_signature.get_public_nonce() + &adaptor_point,
+        pre_signature.get_signature() + adaptor_secret,
+    )
+}
+
+/// Recover the adaptor secret `t = s - s'` from a pre-signature and its completed signature.
+pub fn recover_adaptor_secret(pre_signature: &RistrettoSchnorr, signature: &RistrettoSchnorr) -> RistrettoSecretKey {
+    signature.get_signature() - pre_signature.get_signature()
+}
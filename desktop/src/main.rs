@@ -1,5 +1,8 @@
 use core::marker::PhantomData;
-use std::{thread::sleep, time::Duration};
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
 use blake2::Blake2b;
 use borsh::{
@@ -8,20 +11,43 @@ use borsh::{
 };
 use digest::{consts::U32, Digest};
 use ledger::LedgerWallet;
-use ledger_transport::APDUCommand;
+use ledger_transport::{APDUAnswer, APDUCommand};
+use ledger_transport_hid::TransportNativeHID;
 use ledger_zondax_generic::{App, AppExt};
 use rand::rngs::OsRng;
 use tari_crypto::{
+    commitment::{ExtendedHomomorphicCommitmentFactory, ExtensionDegree, HomomorphicCommitmentFactory},
     hash_domain,
     hashing::DomainSeparation,
     keys::{PublicKey, SecretKey},
-    ristretto::{pedersen::PedersenCommitment, RistrettoPublicKey, RistrettoSchnorr, RistrettoSecretKey},
+    ristretto::{
+        pedersen::{extended_commitment_factory::ExtendedPedersenCommitmentFactory, PedersenCommitment},
+        RistrettoPublicKey,
+        RistrettoSchnorr,
+        RistrettoSecretKey,
+    },
     tari_utilities::{hex::Hex, ByteArray},
 };
 
-use crate::ledger::TRANSPORT;
+use crate::{
+    adaptor::{adaptor_challenge, complete_adaptor_signature, recover_adaptor_secret, verify_adaptor_signature},
+    ledger::TRANSPORT,
+    stealth::{stealth_script_public_key, stealth_shared_secret, tari_address_bytes, ESMERALDA},
+    transaction::{
+        kernel_challenge,
+        metadata_challenge,
+        script_challenge,
+        transaction_excess,
+        TransactionSteps,
+        EMPTY_COVENANT,
+        STANDARD_OUTPUT_FEATURES,
+    },
+};
 
+mod adaptor;
 mod ledger;
+mod stealth;
+mod transaction;
 
 struct LedgerApp;
 
@@ -32,6 +58,57 @@ impl App for LedgerApp {
 const EXPECTED_NAME: &str = "tari_ledger_demo";
 const EXPECTED_PACKAGE: &str = "0.0.1";
 
+// The length of the encrypted value and mask of an output: tag, nonce, value and mask
+const ENCRYPTED_DATA_LENGTH: usize = 16 + 24 + 8 + 32;
+// The status word returned when encrypted data fails authentication
+const DECRYPT_FAILED: u16 = 0x9d60;
+// The status word returned when a transaction does not conserve value
+const TRANSACTION_NOT_BALANCED: u16 = 0x9250;
+// The status word returned when an input does not open to its commitment
+const COMMITMENT_MISMATCH: u16 = 0x9240;
+// The status word returned for a transaction without inputs or outputs
+const EMPTY_TRANSACTION: u16 = 0x9340;
+// The status word returned when a key the device would sign or release with is zero
+const ZERO_KEY: u16 = 0x9350;
+// The status word returned for output metadata that is missing or has no output to be bound to
+const METADATA_NOT_BOUND: u16 = 0x9360;
+// The status word returned for output metadata sent again for an output that already has it
+const METADATA_ALREADY_BOUND: u16 = 0x93c0;
+// The status word returned when a transaction is asked for an output signature before the user approved it
+const TRANSACTION_NOT_APPROVED: u16 = 0x9380;
+// The status word returned when the user rejects a transaction on the device
+const TRANSACTION_REJECTED: u16 = 0x92f0;
+// The status word returned when a claimed change output is not the account's own
+const CHANGE_VERIFICATION_FAILED: u16 = 0x9310;
+// The status word returned for an opaque challenge while blind signing is turned off
+const BLIND_SIGNING_DISABLED: u16 = 0x9330;
+// The status word returned when a public key does not match the key the device derives
+const KEY_MISMATCH: u16 = 0x9280;
+// The status word returned for an address with a bad checksum or an unknown network
+const INVALID_ADDRESS: u16 = 0x9290;
+// The length of a scanned output record: the sender's public nonce, the output commitment and the encrypted data
+const STEALTH_RECORD_LENGTH: usize = 32 + 32 + ENCRYPTED_DATA_LENGTH;
+// The status word returned for a shared secret label that is not whitelisted
+const UNKNOWN_LABEL: u16 = 0x92b0;
+// The shared secret labels, as whitelisted by the device
+const LABEL_DATA_ENCRYPTION_KEY: u8 = 0x00;
+const LABEL_STEALTH_ADDRESS: u8 = 0x01;
+// The status word returned for a script that is empty or cut short
+const INVALID_SCRIPT: u16 = 0x92c0;
+// The TariScript opcodes of one-sided outputs, which may be time-locked, and of padded scripts
+const OP_CHECK_HEIGHT_VERIFY: u8 = 0x66;
+const OP_DROP: u8 = 0x70;
+const OP_PUSH_ONE: u8 = 0x7c;
+const OP_PUSH_PUBKEY: u8 = 0x7e;
+// The status words returned for output features and covenants that do not decode
+const INVALID_OUTPUT_FEATURES: u16 = 0x92d0;
+const INVALID_COVENANT: u16 = 0x92e0;
+
+// The round counts that the `Benchmark` instruction is timed with
+const BENCHMARK_ROUNDS: (u8, u8) = (1, 51);
+// The status word returned for an instruction the app does not know, or was built without
+const BAD_INSTRUCTION: u16 = 0x6e01;
+
 hash_domain!(TransactionHashDomain, "com.tari.base_layer.core.transactions", 0);
 
 enum Instruction {
@@ -41,6 +118,57 @@ enum Instruction {
     GetPublicKey,
     GetPublicNonce,
     Exit,
+    SignAdaptor,
+    RecoverAdaptorSecret,
+    EncryptData,
+    DecryptData,
+    SignTransaction,
+    OneSidedPayment,
+    ScanOutputs,
+    SharedSecret,
+    Benchmark,
+}
+
+// The key branches whose public keys `GetPublicKey` returns, selected by P1
+#[derive(Clone, Copy)]
+enum KeyBranch {
+    Spend = 0x00,
+    DataEncryption = 0x02,
+    ScriptKey = 0x03,
+}
+
+// The key `EncryptData` and `DecryptData` encrypt with, selected by P1
+#[derive(Clone, Copy)]
+enum EncryptionKey<'a> {
+    /// The view key itself
+    ViewKey,
+    /// The view key's Diffie-Hellman shared secret with a public key
+    SharedSecret(&'a RistrettoPublicKey),
+    /// The shared secret of a one-sided payment, with the sealed nonce `OneSidedPayment` replied with and the
+    /// recipient's public key
+    OneSided(&'a [u8], &'a RistrettoPublicKey),
+}
+
+impl EncryptionKey<'_> {
+    fn p1(self) -> u8 {
+        match self {
+            Self::ViewKey => 0x00,
+            Self::SharedSecret(_) => 0x01,
+            Self::OneSided(..) => 0x02,
+        }
+    }
+
+    // Append the fields that select the key to the APDU data
+    fn extend_data(self, data: &mut Vec<u8>) {
+        match self {
+            Self::ViewKey => {},
+            Self::SharedSecret(public_key) => data.extend_from_slice(public_key.as_bytes()),
+            Self::OneSided(sealed_nonce, public_key) => {
+                data.extend_from_slice(sealed_nonce);
+                data.extend_from_slice(public_key.as_bytes());
+            },
+        }
+    }
 }
 
 impl Instruction {
@@ -52,6 +180,15 @@ impl Instruction {
             Self::GetPublicKey => 0x04,
             Self::GetPublicNonce => 0x05,
             Self::Exit => 0x06,
+            Self::SignAdaptor => 0x07,
+            Self::RecoverAdaptorSecret => 0x08,
+            Self::EncryptData => 0x09,
+            Self::DecryptData => 0x0a,
+            Self::SignTransaction => 0x0b,
+            Self::OneSidedPayment => 0x0c,
+            Self::ScanOutputs => 0x0d,
+            Self::SharedSecret => 0x0e,
+            Self::Benchmark => 0x0f,
         }
     }
 }
@@ -135,6 +272,10 @@ fn main() {
             return;
         },
     };
+    if result.retcode() == BLIND_SIGNING_DISABLED {
+        println!("\nError: 'Sign' needs blind signing, turn it on under Settings on the device\n");
+        return;
+    }
     if result.data().len() < 97 {
         println!("\nError: 'Sign' insufficient response! ({:?})\n", result);
         return;
@@ -166,38 +307,930 @@ fn main() {
     println!("sign:       {}", result);
     println!(" ");
 
-    // Commitment
+    // SignAdaptor
     sleep(Duration::from_millis(2000));
-    let value: u64 = 60;
-    let value_bytes = value.to_le_bytes();
+    let adaptor_secret = RistrettoSecretKey::random(&mut OsRng);
+    let adaptor_point = RistrettoPublicKey::from_secret_key(&adaptor_secret);
+    let mut data = challenge_bytes.to_vec();
+    data.extend_from_slice(adaptor_point.as_bytes());
     let command = APDUCommand {
         cla: 0x80,
-        ins: Instruction::Commitment.as_u8(),
+        ins: Instruction::SignAdaptor.as_u8(),
         p1: 0x00,
         p2: 0x00,
-        data: value_bytes.as_bytes().clone(),
+        data,
     };
     let result = match transport.exchange(&command) {
         Ok(result) => result,
         Err(e) => {
-            println!("\nError: Commitment {}\n", e);
+            println!("\nError: SignAdaptor {}\n", e);
+            return;
+        },
+    };
+    if result.retcode() == BLIND_SIGNING_DISABLED {
+        println!("\nError: 'SignAdaptor' needs blind signing, turn it on under Settings on the device\n");
+        return;
+    }
+    if result.data().len() < 97 {
+        println!("\nError: 'SignAdaptor' insufficient response! ({:?})\n", result);
+        return;
+    }
+
+    let public_key = RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap();
+    let sig = RistrettoSecretKey::from_bytes(&result.data()[33..65]).unwrap();
+    let nonce = RistrettoPublicKey::from_bytes(&result.data()[65..97]).unwrap();
+    let pre_signature = RistrettoSchnorr::new(nonce, sig);
+    let result = verify_adaptor_signature(&public_key, &pre_signature, &adaptor_point, &challenge_bytes);
+    println!("pre-signature: {}", pre_signature.get_signature().to_hex());
+    println!("adaptor point: {}", adaptor_point.to_hex());
+    println!("pre-sign:      {}", result);
+
+    let signature = complete_adaptor_signature(&pre_signature, &adaptor_secret);
+    let e = adaptor_challenge(
+        &public_key,
+        pre_signature.get_public_nonce(),
+        &adaptor_point,
+        &challenge_bytes,
+    );
+    let result = signature.verify(&public_key, &e);
+    println!("signature:     {}", signature.get_signature().to_hex());
+    println!("complete:      {}", result);
+    let result = recover_adaptor_secret(&pre_signature, &signature) == adaptor_secret;
+    println!("recover:       {}", result);
+
+    // RecoverAdaptorSecret
+    sleep(Duration::from_millis(2000));
+    let mut data = adaptor_point.as_bytes().to_vec();
+    data.extend_from_slice(pre_signature.get_signature().as_bytes());
+    data.extend_from_slice(signature.get_signature().as_bytes());
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::RecoverAdaptorSecret.as_u8(),
+        p1: 0x00,
+        p2: 0x00,
+        data,
+    };
+    let result = match transport.exchange(&command) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("\nError: RecoverAdaptorSecret {}\n", e);
             return;
         },
     };
     if result.data().len() < 33 {
-        println!("\nError: 'Commitment' insufficient response! ({:?})\n", result);
+        println!(
+            "\nError: 'RecoverAdaptorSecret' insufficient response! ({:?})\n",
+            result
+        );
         return;
     }
+    let recovered = RistrettoSecretKey::from_bytes(&result.data()[1..33]).unwrap();
+    println!("device recover: {}", recovered == adaptor_secret);
+    println!(" ");
 
-    let commitment = &result.data()[1..33];
-    let commitment = PedersenCommitment::from_bytes(commitment).unwrap();
+    // Commitment
+    sleep(Duration::from_millis(2000));
+    let value: u64 = 60;
+    let Some(commitment) = get_commitment(transport, value, 0, 1) else {
+        return;
+    };
     println!("commitment: {}", commitment.to_hex());
+
+    // The mask is derived from the output index only, so `C(v) - C(0)` for the same index must be `v.H`
+    let Some(zero_commitment) = get_commitment(transport, 0, 0, 1) else {
+        return;
+    };
+    let value_commitment =
+        ExtendedPedersenCommitmentFactory::default().commit_value(&RistrettoSecretKey::default(), value);
+    let result = commitment.as_public_key() - zero_commitment.as_public_key() == *value_commitment.as_public_key();
+    println!("tari_crypto commitment: {}", result);
+
+    // Each output index has its own mask, so equal values must not give equal commitments
+    let Some(next_commitment) = get_commitment(transport, value, 1, 1) else {
+        return;
+    };
+    println!("commitment: {}", next_commitment.to_hex());
+    println!("unlinkable: {}", next_commitment != commitment);
+    println!();
+
+    // Extended commitments have the same value generator, so `C(v) - C(0)` for the same index must still be `v.H`
+    let Some(extended_commitment) = get_commitment(transport, value, 0, 3) else {
+        return;
+    };
+    let Some(zero_commitment) = get_commitment(transport, 0, 0, 3) else {
+        return;
+    };
+    println!("extended commitment: {}", extended_commitment.to_hex());
+    let result =
+        extended_commitment.as_public_key() - zero_commitment.as_public_key() == *value_commitment.as_public_key();
+    println!("extended value:      {}", result);
+    println!("extended masks:      {}", extended_commitment != commitment);
+    println!();
+
+    // EncryptData
+    sleep(Duration::from_millis(2000));
+    let output_index = 2;
+    let Some((commitment, encrypted_data)) = encrypt_data(transport, value, output_index, 1, EncryptionKey::ViewKey)
+    else {
+        return;
+    };
+    println!("encrypted data: {}", encrypted_data.to_hex());
+    let Some(expected_commitment) = get_commitment(transport, value, output_index, 1) else {
+        return;
+    };
+    println!("commitment:     {}", commitment == expected_commitment);
+
+    // DecryptData
+    sleep(Duration::from_millis(2000));
+    let Some(result) = decrypt_data(transport, &commitment, &encrypted_data, 1, EncryptionKey::ViewKey) else {
+        return;
+    };
+    // The device checks that the value and mask open the commitment, and only returns the value
+    if result.data().len() < 9 {
+        println!("\nError: 'DecryptData' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let decrypted_value = u64::from_le_bytes(result.data()[1..9].try_into().unwrap());
+    println!("decrypted value: {}", decrypted_value);
+    println!(
+        "decrypt:         {}",
+        decrypted_value == value && result.data().len() == 9
+    );
+
+    // Altered data must fail authentication
+    let mut altered_data = encrypted_data.clone();
+    altered_data[40] ^= 0x01;
+    let Some(result) = decrypt_data(transport, &commitment, &altered_data, 1, EncryptionKey::ViewKey) else {
+        return;
+    };
+    println!("altered:         {}", result.retcode() == DECRYPT_FAILED);
+
+    // Data encrypted with a Diffie-Hellman shared secret can only be decrypted with the same public key
+    let sender_key = RistrettoSecretKey::random(&mut OsRng);
+    let sender_public_key = RistrettoPublicKey::from_secret_key(&sender_key);
+    println!("Approve sharing the output's mask with the sender on the device");
+    let Some((commitment, encrypted_data)) = encrypt_data(
+        transport,
+        value,
+        output_index,
+        1,
+        EncryptionKey::SharedSecret(&sender_public_key),
+    ) else {
+        return;
+    };
+    let Some(result) = decrypt_data(
+        transport,
+        &commitment,
+        &encrypted_data,
+        1,
+        EncryptionKey::SharedSecret(&sender_public_key),
+    ) else {
+        return;
+    };
+    println!("shared secret:   {}", result.retcode() == 0x9000);
+    let other_public_key = RistrettoPublicKey::from_secret_key(&RistrettoSecretKey::random(&mut OsRng));
+    let Some(result) = decrypt_data(
+        transport,
+        &commitment,
+        &encrypted_data,
+        1,
+        EncryptionKey::SharedSecret(&other_public_key),
+    ) else {
+        return;
+    };
+    println!("other key:       {}", result.retcode() == DECRYPT_FAILED);
+    // The device recovers the mask again to spend the output, without it ever leaving the device
+    let Some(script_public_key) = get_public_key(transport, KeyBranch::ScriptKey, 10) else {
+        return;
+    };
+    let steps = TransactionSteps::new(0, 0)
+        .recovered_input(
+            1,
+            &commitment,
+            &encrypted_data,
+            (10, &script_public_key),
+            EncryptionKey::SharedSecret(&sender_public_key),
+        )
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("recovered input: {}", result.retcode() == 0x9000);
+
+    // The encrypted data of an extended commitment holds its first mask, from which the device derives the others
+    let Some((commitment, encrypted_data)) = encrypt_data(transport, value, output_index, 3, EncryptionKey::ViewKey)
+    else {
+        return;
+    };
+    let Some(expected_commitment) = get_commitment(transport, value, output_index, 3) else {
+        return;
+    };
+    println!("extended:        {}", commitment == expected_commitment);
+    let Some(result) = decrypt_data(transport, &commitment, &encrypted_data, 3, EncryptionKey::ViewKey) else {
+        return;
+    };
+    println!("extended decrypt: {}", result.retcode() == 0x9000);
+    let Some(result) = decrypt_data(transport, &commitment, &encrypted_data, 1, EncryptionKey::ViewKey) else {
+        return;
+    };
+    println!("wrong degree:    {}", result.retcode() == DECRYPT_FAILED);
+    println!();
+
+    // SignTransaction
+    sleep(Duration::from_millis(2000));
+    let (fee, lock_height) = (30u64, 0u64);
+    let inputs = [(100u64, 10u64), (50, 11)];
+    let outputs = [(100u64, 12u64)];
+    // A one-sided payment to someone else, whose output the device builds from the nonce at the payment index
+    let payee_key = RistrettoSecretKey::random(&mut OsRng);
+    let payee_public_key = RistrettoPublicKey::from_secret_key(&payee_key);
+    let (payee_address, paid) = (tari_address_bytes(ESMERALDA, &payee_public_key), 20u64);
+    // The output is change, paying to the script key at index 1, so the device does not show it for review
+    let Some(change_public_key) = get_public_key(transport, KeyBranch::ScriptKey, 1) else {
+        return;
+    };
+    let mut change_script = vec![OP_PUSH_PUBKEY];
+    change_script.extend_from_slice(change_public_key.as_bytes());
+    let mut input_commitments = Vec::new();
+    for (value, index) in inputs {
+        let Some(commitment) = get_commitment(transport, value, index, 1) else {
+            return;
+        };
+        input_commitments.push(commitment);
+    }
+    let mut output_commitments = Vec::new();
+    for (value, index) in outputs {
+        let Some(commitment) = get_commitment(transport, value, index, 1) else {
+            return;
+        };
+        output_commitments.push(commitment);
+    }
+    let script_key_indices = [10u64, 11];
+    let mut script_public_keys = Vec::new();
+    for index in script_key_indices {
+        let Some(public_key) = get_public_key(transport, KeyBranch::ScriptKey, index) else {
+            return;
+        };
+        script_public_keys.push(public_key);
+    }
+    // Every input is streamed with its script key, while the device generates the sender offset key of every output
+    let transaction_steps = |fee: u64, script_public_keys: &[RistrettoPublicKey]| {
+        let mut steps = TransactionSteps::new(fee, lock_height);
+        for (((value, index), commitment), script_key) in inputs
+            .iter()
+            .zip(&input_commitments)
+            .zip(script_key_indices.into_iter().zip(script_public_keys))
+        {
+            steps = steps.input(*value, *index, 1, commitment, script_key);
+        }
+        for ((value, index), commitment) in outputs.iter().zip(&output_commitments) {
+            steps = steps
+                .change(*value, *index, 1, commitment, 1, &change_script)
+                .output_features(&STANDARD_OUTPUT_FEATURES)
+                .covenant(&EMPTY_COVENANT);
+        }
+        steps.payment(&payee_address, paid).into_steps()
+    };
+    // The excess is only known once the device replied with the commitment of the payment's output, so the kernel
+    // signature is asked for apart from the other steps
+    let sign = |fee: u64, script_public_keys: &[RistrettoPublicKey]| {
+        let steps = transaction_steps(fee, script_public_keys);
+        let p1s = steps.iter().map(|(p1, _)| *p1).collect::<Vec<_>>();
+        let mut answers = stream_transaction(transport, steps)?;
+        // The sender offset public keys of the change and of the payment, from the device's replies
+        let sender_offset_public_keys = p1s
+            .iter()
+            .zip(&answers)
+            .filter_map(|(p1, answer)| match p1 {
+                0x0a => answer.data().get(1..33),
+                0x09 => answer.data().get(97..129),
+                _ => None,
+            })
+            .map(|bytes| RistrettoPublicKey::from_bytes(bytes).unwrap())
+            .collect::<Vec<_>>();
+        let result = answers.pop()?;
+        if result.retcode() != 0x9000 || result.data().len() < 129 {
+            return Some((result, None));
+        }
+        let payment_commitment = PedersenCommitment::from_bytes(&result.data()[1..33]).unwrap();
+        let outputs = [output_commitments.clone(), vec![payment_commitment]].concat();
+        let excess = transaction_excess(&input_commitments, &outputs, fee);
+        let payment = result.data()[1..97].to_vec();
+        let result = sign_transaction(transport, vec![TransactionSteps::kernel_signature(&excess)])?;
+        Some((result, Some((excess, payment, sender_offset_public_keys))))
+    };
+
+    println!("Review and approve the transaction on the device");
+    let Some((result, payment)) = sign(fee, &script_public_keys) else {
+        return;
+    };
+    if result.retcode() == TRANSACTION_REJECTED {
+        println!("\nError: 'SignTransaction' rejected on the device\n");
+        return;
+    }
+    let Some((excess, payment, sender_offset_public_keys)) = payment else {
+        println!("\nError: 'SignTransaction' payment failed! ({:?})\n", result);
+        return;
+    };
+    if result.data().len() < 97 {
+        println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let sig = RistrettoSecretKey::from_bytes(&result.data()[1..33]).unwrap();
+    let nonce = RistrettoPublicKey::from_bytes(&result.data()[33..65]).unwrap();
+    let signature = RistrettoSchnorr::new(nonce, sig);
+    let e = kernel_challenge(signature.get_public_nonce(), &excess, fee, lock_height);
+    println!("excess:          {}", excess.to_hex());
+    println!("kernel sign:     {}", signature.verify(&excess, &e));
+
+    // The script offset must be `Σ script keys - Σ sender offset keys`
+    let script_offset = RistrettoSecretKey::from_bytes(&result.data()[65..97]).unwrap();
+    let script_offset_public_key = script_public_keys
+        .iter()
+        .fold(RistrettoPublicKey::default(), |sum, key| &sum + key);
+    let script_offset_public_key = sender_offset_public_keys
+        .iter()
+        .fold(script_offset_public_key, |sum, key| &sum - key);
+    let result = RistrettoPublicKey::from_secret_key(&script_offset) == script_offset_public_key;
+    println!("script offset:   {}", result);
+
+    // The payment's output must pay the payee, who opens it and finds its script key from the public nonce alone
+    let payment_commitment = PedersenCommitment::from_bytes(&payment[..32]).unwrap();
+    let public_nonce = RistrettoPublicKey::from_bytes(&payment[32..64]).unwrap();
+    let script_public_key = RistrettoPublicKey::from_bytes(&payment[64..96]).unwrap();
+    let payee_mask = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("commitment_mask")
+        .chain(&(&public_nonce * &payee_key).as_bytes())
+        .finalize();
+    let payee_mask = RistrettoSecretKey::from_bytes(&payee_mask).unwrap();
+    let shared = stealth_shared_secret(&payee_key, &public_nonce);
+    let result = ExtendedPedersenCommitmentFactory::default().commit_value(&payee_mask, paid) == payment_commitment &&
+        stealth_script_public_key(&shared, &payee_public_key) == script_public_key;
+    println!("payment output:  {}", result);
+
+    // Once approved, the device also attests the script of every input, bound to the kernel excess
+    for (position, (commitment, public_key)) in input_commitments.iter().zip(&script_public_keys).enumerate() {
+        let Some(result) = sign_transaction(transport, vec![TransactionSteps::script_attestation(position as u8)])
+        else {
+            return;
+        };
+        if result.data().len() < 65 {
+            println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+            return;
+        }
+        let signature = RistrettoSchnorr::new(
+            RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap(),
+            RistrettoSecretKey::from_bytes(&result.data()[33..65]).unwrap(),
+        );
+        let e = script_challenge(signature.get_public_nonce(), public_key, commitment, &excess);
+        println!("script attest {}: {}", position, signature.verify(public_key, &e));
+    }
+
+    // And it attests the metadata of each output with its sender offset key, which binds the reviewed script, features
+    // and covenant to the output's commitment. The payment's output has the stealth script that pays the payee.
+    let mut stealth_script = vec![OP_PUSH_PUBKEY];
+    stealth_script.extend_from_slice(public_nonce.as_bytes());
+    stealth_script.push(OP_DROP);
+    stealth_script.push(OP_PUSH_PUBKEY);
+    stealth_script.extend_from_slice(script_public_key.as_bytes());
+    let output_metadata = [
+        (&output_commitments[0], &change_script),
+        (&payment_commitment, &stealth_script),
+    ];
+    for (position, ((commitment, script), public_key)) in
+        output_metadata.iter().zip(&sender_offset_public_keys).enumerate()
+    {
+        let Some(result) = sign_transaction(transport, vec![TransactionSteps::metadata_attestation(position as u8)])
+        else {
+            return;
+        };
+        if result.data().len() < 65 {
+            println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+            return;
+        }
+        let signature = RistrettoSchnorr::new(
+            RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap(),
+            RistrettoSecretKey::from_bytes(&result.data()[33..65]).unwrap(),
+        );
+        let e = metadata_challenge(
+            signature.get_public_nonce(),
+            public_key,
+            commitment,
+            script,
+            &STANDARD_OUTPUT_FEATURES,
+            &EMPTY_COVENANT,
+        );
+        println!("metadata attest {}: {}", position, signature.verify(public_key, &e));
+    }
+
+    // A transaction of extended commitments is signed with one signature scalar per blinding generator, which must
+    // satisfy `Σ s_j.G_j = R + e.X`
+    println!("Review and approve the extended transaction on the device");
+    let extension_degree = ExtensionDegree::AddTwoBasePoints;
+    let (value, input_index, output_index) = (100u64, 20u64, 21u64);
+    let Some(input_commitment) = get_commitment(transport, value, input_index, extension_degree as u8) else {
+        return;
+    };
+    let Some(output_commitment) = get_commitment(transport, value - fee, output_index, extension_degree as u8) else {
+        return;
+    };
+    let extended_excess = transaction_excess(
+        std::slice::from_ref(&input_commitment),
+        std::slice::from_ref(&output_commitment),
+        fee,
+    );
+    let steps = TransactionSteps::new(fee, lock_height)
+        .input(
+            value,
+            input_index,
+            extension_degree as u8,
+            &input_commitment,
+            (script_key_indices[0], &script_public_keys[0]),
+        )
+        .change(
+            value - fee,
+            output_index,
+            extension_degree as u8,
+            &output_commitment,
+            1,
+            &change_script,
+        )
+        .finalize(&extended_excess);
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    let signatures_end = 97 + 32 * (extension_degree as usize - 1);
+    if result.data().len() < signatures_end {
+        println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let nonce = RistrettoPublicKey::from_bytes(&result.data()[33..65]).unwrap();
+    let signatures = [&result.data()[1..33], &result.data()[97..signatures_end]]
+        .concat()
+        .chunks(32)
+        .map(|bytes| RistrettoSecretKey::from_bytes(bytes).unwrap())
+        .collect::<Vec<_>>();
+    let e = kernel_challenge(&nonce, &extended_excess, fee, lock_height);
+    let lhs = ExtendedPedersenCommitmentFactory::new_with_extension_degree(extension_degree)
+        .unwrap()
+        .commit_extended(&signatures, &RistrettoSecretKey::default())
+        .unwrap();
+    println!(
+        "extended kernel: {}",
+        *lhs.as_public_key() == &nonce + &(&e * &extended_excess)
+    );
+
+    // A transaction that does not conserve value must not be signed, even when the excess matches the commitments
+    let Some((result, _)) = sign(fee - 10, &script_public_keys) else {
+        return;
+    };
+    println!("unbalanced:      {}", result.retcode() == TRANSACTION_NOT_BALANCED);
+
+    // A transaction without inputs or outputs must not be signed
+    let steps = TransactionSteps::new(0, lock_height).finalize(&RistrettoPublicKey::default());
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("empty:           {}", result.retcode() == EMPTY_TRANSACTION);
+
+    // Nor one whose masks cancel out, as the kernel would be signed by the zero key
+    let (value, index) = inputs[0];
+    let Some(zero_excess_commitment) = get_commitment(transport, value - fee, index, 1) else {
+        return;
+    };
+    let steps = TransactionSteps::new(fee, lock_height)
+        .input(
+            value,
+            index,
+            1,
+            &input_commitments[0],
+            (script_key_indices[0], &script_public_keys[0]),
+        )
+        .change(value - fee, index, 1, &zero_excess_commitment, 1, &change_script)
+        .finalize(&RistrettoPublicKey::default());
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("zero excess:     {}", result.retcode() == ZERO_KEY);
+
+    // Script keys the device does not hold must be rejected, and as every input and output adds one key of its own, the
+    // script offset never sums up to a single key the host could then spend with
+    let wrong_script_public_keys = [script_public_keys[1].clone(), script_public_keys[0].clone()];
+    let Some((result, _)) = sign(fee, &wrong_script_public_keys) else {
+        return;
+    };
+    println!("wrong key:       {}", result.retcode() == KEY_MISMATCH);
+
+    // Change that pays to someone else's key must be rejected
+    let mut foreign_script = vec![OP_PUSH_PUBKEY];
+    foreign_script.extend_from_slice(payee_public_key.as_bytes());
+    let (value, index) = outputs[0];
+    let steps = TransactionSteps::new(fee, lock_height)
+        .change(value, index, 1, &output_commitments[0], 1, &foreign_script)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("foreign change:  {}", result.retcode() == CHANGE_VERIFICATION_FAILED);
+
+    // As must a change script that leaves more than the key on the stack
+    let mut padded_script = vec![OP_PUSH_ONE];
+    padded_script.extend_from_slice(&change_script);
+    let steps = TransactionSteps::new(fee, lock_height)
+        .change(value, index, 1, &output_commitments[0], 1, &padded_script)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("padded change:   {}", result.retcode() == CHANGE_VERIFICATION_FAILED);
+
+    // Change is spent like any other input of the account, with the script key its script pushes
+    let steps = TransactionSteps::new(fee, lock_height)
+        .input(value, index, 1, &output_commitments[0], (1, &change_public_key))
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("change as input: {}", result.retcode() == 0x9000);
+
+    // Outputs are only signed once the user approved the transaction
+    let mut steps = TransactionSteps::new(fee, lock_height)
+        .change(value, index, 1, &output_commitments[0], 1, &change_script)
+        .into_steps();
+    steps.push(TransactionSteps::metadata_attestation(0));
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("not approved:    {}", result.retcode() == TRANSACTION_NOT_APPROVED);
+    let mut steps = TransactionSteps::new(fee, lock_height)
+        .input(
+            value,
+            index,
+            1,
+            &output_commitments[0],
+            (script_key_indices[0], &script_public_keys[0]),
+        )
+        .into_steps();
+    steps.push(TransactionSteps::script_attestation(0));
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("not attested:    {}", result.retcode() == TRANSACTION_NOT_APPROVED);
+
+    // Outputs that are not change are shown with their value and what their script does: this one pays to a key once
+    // the chain reaches height 1000
+    println!("Review and approve the output and its script on the device");
+    let mut script = vec![OP_CHECK_HEIGHT_VERIFY];
+    script.extend_from_slice(&1000u64.to_le_bytes());
+    script.push(OP_PUSH_PUBKEY);
+    script.extend_from_slice(script_public_keys[0].as_bytes());
+    let (input_value, input_index, output_index) = (inputs[0].0, inputs[0].1, 22u64);
+    let Some(output_commitment) = get_commitment(transport, input_value - fee, output_index, 1) else {
+        return;
+    };
+    let steps = TransactionSteps::new(fee, lock_height)
+        .input(
+            input_value,
+            input_index,
+            1,
+            &input_commitments[0],
+            (script_key_indices[0], &script_public_keys[0]),
+        )
+        .output(input_value - fee, output_index, 1, &output_commitment)
+        .output_script(&script)
+        .finalize(&transaction_excess(
+            std::slice::from_ref(&input_commitments[0]),
+            std::slice::from_ref(&output_commitment),
+            fee,
+        ));
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("script review:   {}", result.retcode() == 0x9000);
+
+    // Every script is bound to the output added last, so one without an output, or an output without one, is rejected
+    let output = |steps: TransactionSteps| steps.output(value, index, 1, &output_commitments[0]);
+    let steps = TransactionSteps::new(fee, lock_height)
+        .output_script(&script)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("unbound script:  {}", result.retcode() == METADATA_NOT_BOUND);
+    let steps = output(output(TransactionSteps::new(fee, lock_height))).into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("missing script:  {}", result.retcode() == METADATA_NOT_BOUND);
+
+    // The device generates a fresh sender offset key for every output, even the same output added again, so that the
+    // host can never have one key used twice
+    let mut sender_offset_public_keys = Vec::new();
+    for _ in 0..2 {
+        let steps = output(TransactionSteps::new(fee, lock_height)).into_steps();
+        let Some(result) = sign_transaction(transport, steps) else {
+            return;
+        };
+        if result.data().len() < 33 {
+            println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+            return;
+        }
+        sender_offset_public_keys.push(result.data()[1..33].to_vec());
+    }
+    println!(
+        "fresh offsets:   {}",
+        sender_offset_public_keys[0] != sender_offset_public_keys[1]
+    );
+
+    // A script cut short in an operand must be rejected
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script[..20])
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("invalid script:  {}", result.retcode() == INVALID_SCRIPT);
+
+    // Standard features and an empty covenant need no review, and each is bound to the output added last only once
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script)
+        .output_features(&STANDARD_OUTPUT_FEATURES)
+        .covenant(&EMPTY_COVENANT)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("standard output: {}", result.retcode() == 0x9000);
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script)
+        .output_features(&STANDARD_OUTPUT_FEATURES)
+        .output_features(&STANDARD_OUTPUT_FEATURES)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("features twice:  {}", result.retcode() == METADATA_ALREADY_BOUND);
+
+    // A sidechain feature and the filters of a covenant are each shown for approval
+    println!("Approve the features and the covenant on the device");
+    let mut features = STANDARD_OUTPUT_FEATURES[..14].to_vec();
+    features.extend_from_slice(&[1, 2]); // a confidential output sidechain feature
+    features.extend_from_slice(&32u32.to_le_bytes());
+    features.extend_from_slice(script_public_keys[0].as_bytes()); // the claim public key
+    features.push(0); // range proof type
+
+    // `and(fields_preserved([script]), absolute_height(1000))`, with the field count as a u32 and the height as a u64,
+    // prefixed by the length of the tokens as a varint
+    let mut tokens = vec![0x21u8, 0x31, 0x08, 0x01, 0x00, 0x00, 0x00, 0x01, 0x34, 0x06];
+    tokens.extend_from_slice(&1000u64.to_le_bytes());
+    let mut covenant = vec![tokens.len() as u8];
+    covenant.extend_from_slice(&tokens);
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script)
+        .output_features(&features)
+        .covenant(&covenant)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("custom output:   {}", result.retcode() == 0x9000);
+
+    // Malformed features and covenants must be rejected, such as an `and` filter missing its second operand
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script)
+        .output_features(&features[..features.len() - 1])
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("bad features:    {}", result.retcode() == INVALID_OUTPUT_FEATURES);
+    let steps = output(TransactionSteps::new(fee, lock_height))
+        .output_script(&script)
+        .covenant(&[2, 0x21, 0x20])
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("bad covenant:    {}", result.retcode() == INVALID_COVENANT);
+    println!();
+
+    // OneSidedPayment
+    sleep(Duration::from_millis(2000));
+    println!("Approve the recipient address on the device");
+    let recipient_key = RistrettoSecretKey::random(&mut OsRng);
+    let recipient_public_key = RistrettoPublicKey::from_secret_key(&recipient_key);
+    let address = tari_address_bytes(ESMERALDA, &recipient_public_key);
+    let Some(result) = one_sided_payment(transport, &address) else {
+        return;
+    };
+    if result.data().len() < 65 + ENCRYPTED_DATA_LENGTH {
+        println!("\nError: 'OneSidedPayment' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let public_nonce = RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap();
+    let script_public_key = RistrettoPublicKey::from_bytes(&result.data()[33..65]).unwrap();
+    let sealed_nonce = result.data()[65..65 + ENCRYPTED_DATA_LENGTH].to_vec();
+    println!("public nonce:      {}", public_nonce.to_hex());
+    println!("script public key: {}", script_public_key.to_hex());
+
+    // The recipient finds the output with `c = H(k.R)` and spends it with `k + c`
+    let shared_secret = stealth_shared_secret(&recipient_key, &public_nonce);
+    let result = stealth_script_public_key(&shared_secret, &recipient_public_key) == script_public_key;
+    println!("stealth address:   {}", result);
+    let result = RistrettoPublicKey::from_secret_key(&(&recipient_key + &shared_secret)) == script_public_key;
+    println!("spendable:         {}", result);
+
+    // Every payment gets a fresh nonce, so that no two payments share `R`
+    println!("Approve the recipient address on the device again");
+    let Some(result) = one_sided_payment(transport, &address) else {
+        return;
+    };
+    println!(
+        "fresh nonce:       {}",
+        result.retcode() == 0x9000 && result.data().get(1..33) != Some(public_nonce.as_bytes())
+    );
+    // The device unseals the nonce of the first payment to derive the key `H(r.K)` of the output's encrypted data,
+    // which the recipient computes as `H(k.R)`, for that payment's recipient only
+    let key = EncryptionKey::OneSided(&sealed_nonce, &recipient_public_key);
+    println!("Approve sharing the output's mask with the recipient on the device");
+    let Some((commitment, encrypted_data)) = encrypt_data(transport, 100, 5, 1, key) else {
+        return;
+    };
+    let Some(result) = decrypt_data(transport, &commitment, &encrypted_data, 1, key) else {
+        return;
+    };
+    println!("encryption key:    {}", result.retcode() == 0x9000);
+    let other_public_key = RistrettoPublicKey::from_secret_key(&RistrettoSecretKey::random(&mut OsRng));
+    let key = EncryptionKey::OneSided(&sealed_nonce, &other_public_key);
+    let Some(result) = decrypt_data(transport, &commitment, &encrypted_data, 1, key) else {
+        return;
+    };
+    println!("other recipient:   {}", result.retcode() == DECRYPT_FAILED);
+
+    // An address for an unknown network, as a mistyped checksum would be, must be rejected
+    let mut invalid_address = address;
+    invalid_address[32] ^= 0x01;
+    let Some(result) = one_sided_payment(transport, &invalid_address) else {
+        return;
+    };
+    println!("invalid address:   {}", result.retcode() == INVALID_ADDRESS);
+    println!();
+
+    // ScanOutputs
+    sleep(Duration::from_millis(2000));
+    // The device pays its own view key with index 1, so that the output's encrypted data is keyed by `H(r.V) = H(v.R)`
+    println!("Approve the address of the view key with index 1 on the device");
+    let Some(view_public_key) = get_public_key(transport, KeyBranch::DataEncryption, 1) else {
+        return;
+    };
+    let (own_index, own_value) = (6u64, 250u64);
+    let Some(result) = one_sided_payment(transport, &tari_address_bytes(ESMERALDA, &view_public_key)) else {
+        return;
+    };
+    if result.data().len() < 65 + ENCRYPTED_DATA_LENGTH {
+        println!("\nError: 'OneSidedPayment' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let own_public_nonce = RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap();
+    let own_sealed_nonce = result.data()[65..65 + ENCRYPTED_DATA_LENGTH].to_vec();
+    let key = EncryptionKey::OneSided(&own_sealed_nonce, &view_public_key);
+    println!("Approve sharing the output's mask with the view key with index 1 on the device");
+    let Some((own_commitment, own_encrypted_data)) = encrypt_data(transport, own_value, own_index, 1, key) else {
+        return;
+    };
+    // The output to the view key with index 1 between the payment to someone else, and the output's nonce and
+    // encrypted data with someone else's commitment, which the encrypted data does not decrypt for
+    let records = [
+        [public_nonce.as_bytes(), commitment.as_bytes(), &encrypted_data].concat(),
+        [
+            own_public_nonce.as_bytes(),
+            own_commitment.as_bytes(),
+            &own_encrypted_data,
+        ]
+        .concat(),
+        [own_public_nonce.as_bytes(), commitment.as_bytes(), &own_encrypted_data].concat(),
+    ];
+    let mut data = 0u64.to_le_bytes().to_vec(); // account
+    data.push(2); // address count
+    let start = Instant::now();
+    let Some(result) = scan_outputs(transport, 0x00, data) else {
+        return;
+    };
+    if result.retcode() != 0x9000 {
+        println!("\nError: 'ScanOutputs' start failed! ({:?})\n", result);
+        return;
+    }
+    // A record does not fit an APDU twice, so each is scanned on its own
+    let mut owned = Vec::new();
+    for record in records {
+        let Some(result) = scan_outputs(transport, 0x01, record) else {
+            return;
+        };
+        if result.data().len() < 1 + 17 {
+            println!("\nError: 'ScanOutputs' insufficient response! ({:?})\n", result);
+            return;
+        }
+        let index = u64::from_le_bytes(result.data()[2..10].try_into().unwrap());
+        let value = u64::from_le_bytes(result.data()[10..18].try_into().unwrap());
+        owned.push((result.data()[1] == 1).then_some((index, value)));
+    }
+    println!("owned outputs:   {:?}", owned);
+    println!("found:           {}", owned == [None, Some((1, own_value)), None]);
+    println!("elapsed:         {:?}", start.elapsed());
+
+    // A record that is cut short must be rejected
+    let Some(result) = scan_outputs(transport, 0x01, vec![0u8; STEALTH_RECORD_LENGTH - 1]) else {
+        return;
+    };
+    println!("short record:    {}", result.retcode() != 0x9000);
+
+    // A one-sided payment to the view key with index 1 is spent with the mask and script key derived from its nonce
+    let steps = TransactionSteps::new(fee, lock_height)
+        .payment(&tari_address_bytes(ESMERALDA, &view_public_key), own_value)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    if result.data().len() < 65 {
+        println!("\nError: 'SignTransaction' insufficient response! ({:?})\n", result);
+        return;
+    }
+    let received_commitment = PedersenCommitment::from_bytes(&result.data()[1..33]).unwrap();
+    let received_public_nonce = RistrettoPublicKey::from_bytes(&result.data()[33..65]).unwrap();
+    let steps = TransactionSteps::new(fee, lock_height)
+        .received_payment(own_value, 1, &received_commitment, &received_public_nonce)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("received input:  {}", result.retcode() == 0x9000);
+    let steps = TransactionSteps::new(fee, lock_height)
+        .received_payment(own_value, 0, &received_commitment, &received_public_nonce)
+        .into_steps();
+    let Some(result) = sign_transaction(transport, steps) else {
+        return;
+    };
+    println!("wrong view key:  {}", result.retcode() == COMMITMENT_MISMATCH);
+    println!();
+
+    // SharedSecret
+    sleep(Duration::from_millis(2000));
+    let Some(device_public_key) = get_public_key(transport, KeyBranch::ScriptKey, 0) else {
+        return;
+    };
+    let host_key = RistrettoSecretKey::random(&mut OsRng);
+    let host_public_key = RistrettoPublicKey::from_secret_key(&host_key);
+    let Some(result) = get_shared_secret(
+        transport,
+        KeyBranch::ScriptKey,
+        &host_public_key,
+        LABEL_DATA_ENCRYPTION_KEY,
+    ) else {
+        return;
+    };
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("data_encryption_key")
+        .chain(&(&device_public_key * &host_key).as_bytes())
+        .finalize();
+    println!("data encryption: {}", result.data().get(1..33) == Some(&hash[..]));
+    let Some(result) = get_shared_secret(transport, KeyBranch::ScriptKey, &host_public_key, LABEL_STEALTH_ADDRESS)
+    else {
+        return;
+    };
+    let shared = stealth_shared_secret(&host_key, &device_public_key);
+    println!(
+        "stealth address: {}",
+        result.data().get(1..33) == Some(shared.as_bytes())
+    );
+
+    // Labels outside the whitelist must be rejected, so that the raw point can never be hashed as the host pleases
+    let Some(result) = get_shared_secret(transport, KeyBranch::ScriptKey, &host_public_key, 0xff) else {
+        return;
+    };
+    println!("unknown label:   {}", result.retcode() == UNKNOWN_LABEL);
+
+    // The secrets of the view key are those of the one-sided payments the account receives, so they take approval
+    println!("Approve the shared secret of the view key on the device");
+    let Some(view_public_key) = get_public_key(transport, KeyBranch::DataEncryption, 0) else {
+        return;
+    };
+    let Some(result) = get_shared_secret(
+        transport,
+        KeyBranch::DataEncryption,
+        &host_public_key,
+        LABEL_DATA_ENCRYPTION_KEY,
+    ) else {
+        return;
+    };
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("data_encryption_key")
+        .chain(&(&view_public_key * &host_key).as_bytes())
+        .finalize();
+    println!("view key secret: {}", result.data().get(1..33) == Some(&hash[..]));
     println!();
 
     // GetPublicKey
     sleep(Duration::from_millis(2000));
     let account_bytes = &ledger.account_public_key().as_bytes()[0..8].to_vec().to_hex(); // We only use the 1st 8 bytes
     let account = u64::from_str_radix(account_bytes, 16).unwrap();
+    let start = Instant::now();
     for i in 0u64..5 {
         let address_index = i.to_le_bytes();
         let mut data = account.to_le_bytes().to_vec();
@@ -229,10 +1262,12 @@ fn main() {
         let public_key = RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap();
         println!("public_key: {}", public_key.to_hex());
     }
+    println!("elapsed:    {:?}", start.elapsed());
     println!();
 
     // GetPublicNonce
     sleep(Duration::from_millis(2000));
+    let start = Instant::now();
     for _i in 0..5 {
         let command = APDUCommand {
             cla: 0x80,
@@ -256,6 +1291,27 @@ fn main() {
         let public_key = RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap();
         println!("public_nonce: {}", public_key.to_hex());
     }
+    println!("elapsed:      {:?}", start.elapsed());
+    println!();
+
+    // Benchmark
+    // Each round is two scalar multiplications, and timing two round counts takes out the APDU round trip. The
+    // instruction is only built in with the `benchmark` feature, so a refusal skips the section.
+    sleep(Duration::from_millis(2000));
+    let Some(few) = benchmark(transport, BENCHMARK_ROUNDS.0) else {
+        return;
+    };
+    if let Some(few) = few {
+        let Some(Some(many)) = benchmark(transport, BENCHMARK_ROUNDS.1) else {
+            return;
+        };
+        let multiplications = 2 * u32::from(BENCHMARK_ROUNDS.1 - BENCHMARK_ROUNDS.0);
+        println!("rounds:          {} and {}", BENCHMARK_ROUNDS.0, BENCHMARK_ROUNDS.1);
+        println!("elapsed:         {:?} and {:?}", few, many);
+        println!("per scalar mult: {:?}", many.saturating_sub(few) / multiplications);
+    } else {
+        println!("skipped:         built without the `benchmark` feature");
+    }
     println!();
 
     // BadInstruction
@@ -289,6 +1345,256 @@ fn main() {
     println!();
 }
 
+// Time the `Benchmark` instruction for `rounds` rounds, or `Some(None)` if the device refuses it as a bad instruction
+fn benchmark(transport: &TransportNativeHID, rounds: u8) -> Option<Option<Duration>> {
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::Benchmark.as_u8(),
+        p1: rounds,
+        p2: 0x00,
+        data: vec![0],
+    };
+    let start = Instant::now();
+    match transport.exchange(&command) {
+        Ok(result) if result.retcode() == 0x9000 && result.data().len() >= 33 => Some(Some(start.elapsed())),
+        Ok(result) if result.retcode() == BAD_INSTRUCTION => Some(None),
+        Ok(result) => {
+            println!("\nError: 'Benchmark' failed! ({:?})\n", result);
+            None
+        },
+        Err(e) => {
+            println!("\nError: Benchmark {}\n", e);
+            None
+        },
+    }
+}
+
+// Get the public key at `index` of `branch` of account 0 from the device
+fn get_public_key(transport: &TransportNativeHID, branch: KeyBranch, index: u64) -> Option<RistrettoPublicKey> {
+    let mut data = 0u64.to_le_bytes().to_vec(); // account
+    data.extend_from_slice(&index.to_le_bytes());
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::GetPublicKey.as_u8(),
+        p1: branch as u8,
+        p2: 0x00,
+        data,
+    };
+    let result = match transport.exchange(&command) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("\nError: GetPublicKey {}\n", e);
+            return None;
+        },
+    };
+    if result.data().len() < 33 {
+        println!("\nError: 'GetPublicKey' insufficient response! ({:?})\n", result);
+        return None;
+    }
+
+    Some(RistrettoPublicKey::from_bytes(&result.data()[1..33]).unwrap())
+}
+
+// Get a commitment to `value` from the device, masked with the commitment masks for `output_index` of account 0, one
+// per blinding generator of an extended commitment of degree `extension_degree`
+fn get_commitment(
+    transport: &TransportNativeHID,
+    value: u64,
+    output_index: u64,
+    extension_degree: u8,
+) -> Option<PedersenCommitment> {
+    let mut data = value.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes()); // account
+    data.extend_from_slice(&output_index.to_le_bytes());
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::Commitment.as_u8(),
+        p1: extension_degree,
+        p2: 0x00,
+        data,
+    };
+    let result = match transport.exchange(&command) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("\nError: Commitment {}\n", e);
+            return None;
+        },
+    };
+    if result.data().len() < 33 {
+        println!("\nError: 'Commitment' insufficient response! ({:?})\n", result);
+        return None;
+    }
+
+    Some(PedersenCommitment::from_bytes(&result.data()[1..33]).unwrap())
+}
+
+// Have the device commit to `value` with the mask for `output_index` of account 0 and encrypt the value and mask with
+// `key`
+fn encrypt_data(
+    transport: &TransportNativeHID,
+    value: u64,
+    output_index: u64,
+    extension_degree: u8,
+    key: EncryptionKey,
+) -> Option<(PedersenCommitment, Vec<u8>)> {
+    let mut data = value.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes()); // account
+    data.extend_from_slice(&output_index.to_le_bytes());
+    key.extend_data(&mut data);
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::EncryptData.as_u8(),
+        p1: key.p1(),
+        p2: extension_degree,
+        data,
+    };
+    let result = match transport.exchange(&command) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("\nError: EncryptData {}\n", e);
+            return None;
+        },
+    };
+    if result.data().len() < 33 + ENCRYPTED_DATA_LENGTH {
+        println!("\nError: 'EncryptData' insufficient response! ({:?})\n", result);
+        return None;
+    }
+
+    let commitment = PedersenCommitment::from_bytes(&result.data()[1..33]).unwrap();
+    Some((commitment, result.data()[33..33 + ENCRYPTED_DATA_LENGTH].to_vec()))
+}
+
+// Have the device decrypt the value and mask of an output of account 0, returning the answer as is so that the caller
+// can inspect the status word
+fn decrypt_data(
+    transport: &TransportNativeHID,
+    commitment: &PedersenCommitment,
+    encrypted_data: &[u8],
+    extension_degree: u8,
+    key: EncryptionKey,
+) -> Option<APDUAnswer<Vec<u8>>> {
+    let mut data = 0u64.to_le_bytes().to_vec(); // account
+    data.extend_from_slice(commitment.as_bytes());
+    data.extend_from_slice(encrypted_data);
+    key.extend_data(&mut data);
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::DecryptData.as_u8(),
+        p1: key.p1(),
+        p2: extension_degree,
+        data,
+    };
+    match transport.exchange(&command) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            println!("\nError: DecryptData {}\n", e);
+            None
+        },
+    }
+}
+
+// Have the device construct a one-sided payment of account 0 to the encoded Tari address `address`, with a fresh nonce,
+// returning the answer as is so that the caller can inspect the status word
+fn one_sided_payment(transport: &TransportNativeHID, address: &[u8]) -> Option<APDUAnswer<Vec<u8>>> {
+    let mut data = 0u64.to_le_bytes().to_vec(); // account
+    data.extend_from_slice(address);
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::OneSidedPayment.as_u8(),
+        p1: 0x00,
+        p2: 0x00,
+        data,
+    };
+    match transport.exchange(&command) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            println!("\nError: OneSidedPayment {}\n", e);
+            None
+        },
+    }
+}
+
+// Stream a transaction to the device and ask for its kernel signature, returning the answer to the final step as is,
+// or the first failing answer, so that the caller can inspect the status word
+fn sign_transaction(transport: &TransportNativeHID, steps: Vec<(u8, Vec<u8>)>) -> Option<APDUAnswer<Vec<u8>>> {
+    stream_transaction(transport, steps)?.pop()
+}
+
+// Stream a transaction to the device like `sign_transaction`, returning the answer to every step up to the final or
+// first failing one
+fn stream_transaction(transport: &TransportNativeHID, steps: Vec<(u8, Vec<u8>)>) -> Option<Vec<APDUAnswer<Vec<u8>>>> {
+    let mut answers = Vec::new();
+    for (p1, data) in steps {
+        let command = APDUCommand {
+            cla: 0x80,
+            ins: Instruction::SignTransaction.as_u8(),
+            p1,
+            p2: 0x00,
+            data,
+        };
+        let result = match transport.exchange(&command) {
+            Ok(result) => result,
+            Err(e) => {
+                println!("\nError: SignTransaction {}\n", e);
+                return None;
+            },
+        };
+        let failed = result.retcode() != 0x9000;
+        answers.push(result);
+        if failed {
+            break;
+        }
+    }
+    Some(answers)
+}
+
+// Send one step of an output scan to the device, returning the answer as is so that the caller can inspect the status
+// word
+fn scan_outputs(transport: &TransportNativeHID, p1: u8, data: Vec<u8>) -> Option<APDUAnswer<Vec<u8>>> {
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::ScanOutputs.as_u8(),
+        p1,
+        p2: 0x00,
+        data,
+    };
+    match transport.exchange(&command) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            println!("\nError: ScanOutputs {}\n", e);
+            None
+        },
+    }
+}
+
+// Have the device hash its shared secret of the script key at index 0 of account 0 with `public_key` under `label`,
+// returning the answer as is so that the caller can inspect the status word
+fn get_shared_secret(
+    transport: &TransportNativeHID,
+    branch: KeyBranch,
+    public_key: &RistrettoPublicKey,
+    label: u8,
+) -> Option<APDUAnswer<Vec<u8>>> {
+    let mut data = 0u64.to_le_bytes().to_vec(); // account
+    data.extend_from_slice(&0u64.to_le_bytes()); // index
+    data.extend_from_slice(public_key.as_bytes());
+    data.push(label);
+    let command = APDUCommand {
+        cla: 0x80,
+        ins: Instruction::SharedSecret.as_u8(),
+        p1: branch as u8,
+        p2: 0x00,
+        data,
+    };
+    match transport.exchange(&command) {
+        Ok(result) => Some(result),
+        Err(e) => {
+            println!("\nError: SharedSecret {}\n", e);
+            None
+        },
+    }
+}
+
 pub struct DomainSeparatedConsensusHasher<M>(PhantomData<M>);
 
 impl<M: DomainSeparation> DomainSeparatedConsensusHasher<M> {
@@ -0,0 +1,61 @@
+use blake2::Blake2b;
+use digest::consts::U64;
+use tari_crypto::{
+    hash_domain,
+    hashing::DomainSeparatedHasher,
+    keys::PublicKey,
+    ristretto::{RistrettoPublicKey, RistrettoSecretKey},
+    tari_utilities::ByteArray,
+};
+
+hash_domain!(StealthAddressDomain, "com.tari.base_layer.wallet.stealth_address", 1);
+
+/// The network byte of an Esmeralda testnet address.
+pub const ESMERALDA: u8 = 0x26;
+
+/// Encode a Tari address as `public key (32) || (checksum ^ network) (1)`.
+pub fn tari_address_bytes(network: u8, public_key: &RistrettoPublicKey) -> Vec<u8> {
+    let mut bytes = public_key.as_bytes().to_vec();
+    bytes.push(dammsum(public_key.as_bytes()) ^ network);
+    bytes
+}
+
+/// Compute the shared secret `c = H(private_key.public_key)` of a stealth output.
+pub fn stealth_shared_secret(private_key: &RistrettoSecretKey, public_key: &RistrettoPublicKey) -> RistrettoSecretKey {
+    let hash = DomainSeparatedHasher::<Blake2b<U64>, StealthAddressDomain>::new_with_label("stealth_address")
+        .chain((public_key * private_key).as_bytes())
+        .finalize();
+    from_uniform_bytes(hash.as_ref())
+}
+
+/// Compute the stealth script key `K_s = c.G + K` for the shared secret `c` and the recipient's public key `K`.
+pub fn stealth_script_public_key(
+    shared_secret: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+) -> RistrettoPublicKey {
+    RistrettoPublicKey::from_secret_key(shared_secret) + public_key
+}
+
+// Reduce 64 uniformly random bytes modulo the group order, as the device does with `Scalar::from_bytes_mod_order_wide`
+// - `RistrettoSecretKey::from_bytes` only reduces 32 bytes, so the high half is scaled by `2^256 = 2^255 + 2^255`.
+fn from_uniform_bytes(bytes: &[u8]) -> RistrettoSecretKey {
+    let low = RistrettoSecretKey::from_bytes(&bytes[..32]).unwrap();
+    let high = RistrettoSecretKey::from_bytes(&bytes[32..64]).unwrap();
+    let mut power_bytes = [0u8; 32];
+    power_bytes[31] = 0x80;
+    let power = RistrettoSecretKey::from_bytes(&power_bytes).unwrap();
+    &low + &(&high * &(&power + &power))
+}
+
+// Compute the DammSum checksum of `data`
+fn dammsum(data: &[u8]) -> u8 {
+    let mask = 1u8 + (1 << 4) + (1 << 3) + (1 << 1);
+    data.iter().fold(0u8, |result, digit| {
+        let result = result ^ digit;
+        if result & (1 << 7) == 0 {
+            result << 1
+        } else {
+            (result << 1) ^ mask
+        }
+    })
+}
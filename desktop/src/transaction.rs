@@ -0,0 +1,267 @@
+use tari_crypto::{
+    commitment::HomomorphicCommitmentFactory,
+    ristretto::{
+        pedersen::{extended_commitment_factory::ExtendedPedersenCommitmentFactory, PedersenCommitment},
+        RistrettoPublicKey,
+        RistrettoSecretKey,
+    },
+    tari_utilities::ByteArray,
+};
+
+use crate::{DomainSeparatedConsensusHasher, EncryptionKey, TransactionHashDomain};
+
+/// The borsh encoding of the features of a plain output: version, output type, maturity, coinbase extra, no sidechain
+/// feature and range proof type.
+pub const STANDARD_OUTPUT_FEATURES: [u8; 16] = [0; 16];
+/// The borsh encoding of an empty covenant, whose length is a varint.
+pub const EMPTY_COVENANT: [u8; 1] = [0];
+
+/// Compute the excess `Σin - Σout - fee.H` the device expects for a balanced transaction, whose outputs include those
+/// the device built for its payments.
+pub fn transaction_excess(
+    inputs: &[PedersenCommitment],
+    outputs: &[PedersenCommitment],
+    fee: u64,
+) -> RistrettoPublicKey {
+    let fee_commitment = ExtendedPedersenCommitmentFactory::default().commit_value(&RistrettoSecretKey::default(), fee);
+    let inputs = inputs
+        .iter()
+        .fold(RistrettoPublicKey::default(), |sum, c| &sum + c.as_public_key());
+    let outputs = outputs
+        .iter()
+        .fold(RistrettoPublicKey::default(), |sum, c| &sum + c.as_public_key());
+    &(&inputs - &outputs) - fee_commitment.as_public_key()
+}
+
+/// Compute the challenge the device uses for a kernel signature, for a version 0 kernel without features or burn
+/// commitment.
+pub fn kernel_challenge(
+    public_nonce: &RistrettoPublicKey,
+    excess: &RistrettoPublicKey,
+    fee: u64,
+    lock_height: u64,
+) -> RistrettoSecretKey {
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("kernel_signature")
+        .chain(public_nonce)
+        .chain(excess)
+        .chain(&0u8) // version
+        .chain(&fee)
+        .chain(&lock_height)
+        .chain(&0u8) // features
+        .chain(&0u8) // no burn commitment
+        .finalize();
+    RistrettoSecretKey::from_bytes(&hash).unwrap()
+}
+
+/// Compute the challenge the device uses for the metadata attestation of an output with commitment `commitment`, script
+/// `script` and the borsh encoded `features` and `covenant`, by its sender offset key `public_key`.
+pub fn metadata_challenge(
+    public_nonce: &RistrettoPublicKey,
+    public_key: &RistrettoPublicKey,
+    commitment: &PedersenCommitment,
+    script: &[u8],
+    features: &[u8],
+    covenant: &[u8],
+) -> RistrettoSecretKey {
+    let field_hash = |label: &'static str, bytes: &[u8]| {
+        DomainSeparatedConsensusHasher::<TransactionHashDomain>::new(label)
+            .chain(&bytes)
+            .finalize()
+    };
+    let metadata_hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("output_metadata")
+        .chain(&commitment.as_bytes())
+        .chain(&field_hash("output_script", script))
+        .chain(&field_hash("output_features", features))
+        .chain(&field_hash("output_covenant", covenant))
+        .finalize();
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("metadata_attestation")
+        .chain(&public_nonce.as_bytes())
+        .chain(&public_key.as_bytes())
+        .chain(&metadata_hash)
+        .finalize();
+    RistrettoSecretKey::from_bytes(&hash).unwrap()
+}
+
+/// Compute the challenge the device uses for the script attestation of an input with commitment `commitment`, by its
+/// script key `public_key`, in a transaction with the kernel excess `excess`.
+pub fn script_challenge(
+    public_nonce: &RistrettoPublicKey,
+    public_key: &RistrettoPublicKey,
+    commitment: &PedersenCommitment,
+    excess: &RistrettoPublicKey,
+) -> RistrettoSecretKey {
+    let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("script_attestation")
+        .chain(&public_nonce.as_bytes())
+        .chain(&public_key.as_bytes())
+        .chain(&commitment.as_bytes())
+        .chain(&excess.as_bytes())
+        .finalize();
+    RistrettoSecretKey::from_bytes(&hash).unwrap()
+}
+
+/// The APDU steps, as `(P1, data)` pairs, that stream a transaction of account 0 to the device for signing.
+pub struct TransactionSteps {
+    steps: Vec<(u8, Vec<u8>)>,
+}
+
+impl TransactionSteps {
+    /// Start a transaction with the given fee and lock height.
+    pub fn new(fee: u64, lock_height: u64) -> Self {
+        let mut data = 0u64.to_le_bytes().to_vec(); // account
+        data.extend_from_slice(&fee.to_le_bytes());
+        data.extend_from_slice(&lock_height.to_le_bytes());
+        Self {
+            steps: vec![(0x00, data)],
+        }
+    }
+
+    /// Add an input that opens to `value` with the commitment masks at `mask_index` of `extension_degree`, and whose
+    /// script key is the `(index, public key)` pair `script_key`.
+    pub fn input(
+        self,
+        value: u64,
+        mask_index: u64,
+        extension_degree: u8,
+        commitment: &PedersenCommitment,
+        script_key: (u64, &RistrettoPublicKey),
+    ) -> Self {
+        let (key_index, public_key) = script_key;
+        self.opening(0x01, value, mask_index, extension_degree, commitment, |data| {
+            data.extend_from_slice(&key_index.to_le_bytes());
+            data.extend_from_slice(public_key.as_bytes());
+        })
+    }
+
+    /// Add an output that opens to `value` with the commitment masks at `mask_index` of `extension_degree`. The device
+    /// replies with the public key of the sender offset key it generated for the output.
+    pub fn output(self, value: u64, mask_index: u64, extension_degree: u8, commitment: &PedersenCommitment) -> Self {
+        self.opening(0x02, value, mask_index, extension_degree, commitment, |_| {})
+    }
+
+    /// Add a change output like `output`, whose script pushes the script key at `script_key_index`.
+    pub fn change(
+        self,
+        value: u64,
+        mask_index: u64,
+        extension_degree: u8,
+        commitment: &PedersenCommitment,
+        script_key_index: u64,
+        script: &[u8],
+    ) -> Self {
+        self.opening(0x0a, value, mask_index, extension_degree, commitment, |data| {
+            data.extend_from_slice(&script_key_index.to_le_bytes());
+            data.extend_from_slice(script);
+        })
+    }
+
+    /// Add a one-sided payment of `value` to the encoded Tari address `address`, with a nonce the device picks. The
+    /// device replies with the output it built, including the public key of the sender offset key it generated for it.
+    pub fn payment(mut self, address: &[u8], value: u64) -> Self {
+        let mut data = address.to_vec();
+        data.extend_from_slice(&value.to_le_bytes());
+        self.steps.push((0x09, data));
+        self
+    }
+
+    /// Add an input that the device recovers from its encrypted data `encrypted_data`, encrypted with `key`, which is
+    /// either the view key or its shared secret with a public key, and whose script key is the `(index, public key)`
+    /// pair `script_key`.
+    pub fn recovered_input(
+        mut self,
+        extension_degree: u8,
+        commitment: &PedersenCommitment,
+        encrypted_data: &[u8],
+        script_key: (u64, &RistrettoPublicKey),
+        key: EncryptionKey,
+    ) -> Self {
+        let (key_index, public_key) = script_key;
+        let mut data = vec![key.p1(), extension_degree];
+        data.extend_from_slice(commitment.as_bytes());
+        data.extend_from_slice(encrypted_data);
+        data.extend_from_slice(&key_index.to_le_bytes());
+        data.extend_from_slice(public_key.as_bytes());
+        key.extend_data(&mut data);
+        self.steps.push((0x0e, data));
+        self
+    }
+
+    /// Add the one-sided payment of `value` that the view key at `view_key_index` received, with the output's
+    /// commitment and the public nonce `R` of its script, as an input. The device derives the mask and script key
+    /// of the output.
+    pub fn received_payment(
+        mut self,
+        value: u64,
+        view_key_index: u64,
+        commitment: &PedersenCommitment,
+        public_nonce: &RistrettoPublicKey,
+    ) -> Self {
+        let mut data = value.to_le_bytes().to_vec();
+        data.extend_from_slice(&view_key_index.to_le_bytes());
+        data.extend_from_slice(commitment.as_bytes());
+        data.extend_from_slice(public_nonce.as_bytes());
+        self.steps.push((0x0d, data));
+        self
+    }
+
+    /// Have the device review the script of the output added last.
+    pub fn output_script(mut self, script: &[u8]) -> Self {
+        self.steps.push((0x06, script.to_vec()));
+        self
+    }
+
+    /// Have the device review the borsh encoded features of the output added last.
+    pub fn output_features(mut self, features: &[u8]) -> Self {
+        self.steps.push((0x07, features.to_vec()));
+        self
+    }
+
+    /// Have the device review the borsh encoded covenant of the output added last.
+    pub fn covenant(mut self, covenant: &[u8]) -> Self {
+        self.steps.push((0x08, covenant.to_vec()));
+        self
+    }
+
+    /// Return the steps without asking for the kernel signature.
+    pub fn into_steps(self) -> Vec<(u8, Vec<u8>)> {
+        self.steps
+    }
+
+    /// The step that asks an approved transaction for the metadata attestation of the output at `position`.
+    pub fn metadata_attestation(position: u8) -> (u8, Vec<u8>) {
+        (0x0b, vec![position])
+    }
+
+    /// The step that asks an approved transaction for the script attestation of the input at `position`.
+    pub fn script_attestation(position: u8) -> (u8, Vec<u8>) {
+        (0x0c, vec![position])
+    }
+
+    /// The step that asks for the kernel signature for the claimed excess.
+    pub fn kernel_signature(excess: &RistrettoPublicKey) -> (u8, Vec<u8>) {
+        (0x03, excess.as_bytes().to_vec())
+    }
+
+    /// Ask for the kernel signature for the claimed excess, returning all the steps.
+    pub fn finalize(mut self, excess: &RistrettoPublicKey) -> Vec<(u8, Vec<u8>)> {
+        self.steps.push(Self::kernel_signature(excess));
+        self.steps
+    }
+
+    fn opening(
+        mut self,
+        p1: u8,
+        value: u64,
+        mask_index: u64,
+        extension_degree: u8,
+        commitment: &PedersenCommitment,
+        extend: impl FnOnce(&mut Vec<u8>),
+    ) -> Self {
+        let mut data = value.to_le_bytes().to_vec();
+        data.extend_from_slice(&mask_index.to_le_bytes());
+        data.push(extension_degree);
+        data.extend_from_slice(commitment.as_bytes());
+        extend(&mut data);
+        self.steps.push((p1, data));
+        self
+    }
+}
@@ -0,0 +1,24 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Write the compressed "nothing up my sleeve" points of `tari_crypto` that serve as Pedersen generators into the
+//! build, as a constant array that `pedersen.rs` includes, so that the device itself does not depend on `tari_crypto`
+
+use std::{env, fs, path::Path};
+
+use tari_crypto::ristretto::constants::RISTRETTO_NUMS_POINTS_COMPRESSED;
+
+// The value generator `H` and the extra blinding generators of the largest extension degree, see `pedersen.rs`
+const GENERATOR_COUNT: usize = 6;
+
+fn main() {
+    let mut points = String::from("[\n");
+    for point in &RISTRETTO_NUMS_POINTS_COMPRESSED[..GENERATOR_COUNT] {
+        points.push_str(&format!("    CompressedRistretto({:?}),\n", point.to_bytes()));
+    }
+    points.push_str("]\n");
+
+    let out_dir = env::var("OUT_DIR").expect("cargo sets OUT_DIR for build scripts");
+    fs::write(Path::new(&out_dir).join("nums_points.rs"), points).expect("OUT_DIR is writable");
+    println!("cargo:rerun-if-changed=build.rs");
+}
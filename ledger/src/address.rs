@@ -0,0 +1,123 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Tari addresses
+//! A Tari address is the recipient's public key followed by a single byte that combines a checksum of the key with the
+//! network the address is for, i.e. `public key (32) || (checksum ^ network) (1)`, as in `tari_common_types`'
+//! `TariAddress`. The checksum is the DammSum of the key bytes, so a single mistyped byte always changes it.
+
+use crate::{errors::Error, ristretto_keys::RistrettoPublicKey};
+
+/// The length of a byte encoded Tari address
+pub const TARI_ADDRESS_LENGTH: usize = 33;
+
+// The coefficients of the polynomial that defines the DammSum quasigroup over GF(2^8)
+const DAMMSUM_COEFFICIENTS: [u8; 3] = [4, 3, 1];
+
+/// The networks a Tari address can be for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    MainNet = 0x00,
+    StageNet = 0x01,
+    NextNet = 0x02,
+    LocalNet = 0x10,
+    Igor = 0x24,
+    Esmeralda = 0x26,
+}
+
+impl Network {
+    pub fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0x00 => Ok(Self::MainNet),
+            0x01 => Ok(Self::StageNet),
+            0x02 => Ok(Self::NextNet),
+            0x10 => Ok(Self::LocalNet),
+            0x24 => Ok(Self::Igor),
+            0x26 => Ok(Self::Esmeralda),
+            _ => Err(Error::InvalidAddress),
+        }
+    }
+
+    /// The network after this one, in the order the settings menu cycles through them
+    pub fn next(self) -> Self {
+        match self {
+            Self::MainNet => Self::StageNet,
+            Self::StageNet => Self::NextNet,
+            Self::NextNet => Self::LocalNet,
+            Self::LocalNet => Self::Igor,
+            Self::Igor => Self::Esmeralda,
+            Self::Esmeralda => Self::MainNet,
+        }
+    }
+
+    /// The name of the network, as shown to the user
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::MainNet => "mainnet",
+            Self::StageNet => "stagenet",
+            Self::NextNet => "nextnet",
+            Self::LocalNet => "localnet",
+            Self::Igor => "igor",
+            Self::Esmeralda => "esmeralda",
+        }
+    }
+}
+
+/// A Tari address: the recipient's public key on a network
+#[derive(Clone, PartialEq, Eq)]
+pub struct TariAddress {
+    network: Network,
+    public_key: RistrettoPublicKey,
+}
+
+impl TariAddress {
+    pub fn new(network: Network, public_key: RistrettoPublicKey) -> Self {
+        Self { network, public_key }
+    }
+
+    /// Decode an address, checking that its checksum byte is for a known network
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != TARI_ADDRESS_LENGTH {
+            return Err(Error::IncorrectByteLength);
+        }
+        let checksum = dammsum(&bytes[..32]);
+        let network = Network::from_byte(checksum ^ bytes[32])?;
+        let public_key = RistrettoPublicKey::from_bytes(&bytes[..32]).map_err(|_| Error::InvalidAddress)?;
+        Ok(Self { network, public_key })
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn public_key(&self) -> &RistrettoPublicKey {
+        &self.public_key
+    }
+
+    /// Encode the address as `public key (32) || (checksum ^ network) (1)`
+    pub fn to_bytes(&self) -> [u8; TARI_ADDRESS_LENGTH] {
+        let mut bytes = [0u8; TARI_ADDRESS_LENGTH];
+        bytes[..32].copy_from_slice(self.public_key.as_bytes());
+        bytes[32] = dammsum(&bytes[..32]) ^ self.network as u8;
+        bytes
+    }
+}
+
+// Compute the DammSum checksum of `data`
+fn dammsum(data: &[u8]) -> u8 {
+    let mut mask = 1u8;
+    for bit in DAMMSUM_COEFFICIENTS {
+        mask += 1u8 << bit;
+    }
+
+    let mut result = 0u8;
+    for digit in data {
+        result ^= *digit;
+        let overflow = (result & (1 << 7)) != 0;
+        result <<= 1;
+        if overflow {
+            result ^= mask;
+        }
+    }
+    result
+}
@@ -0,0 +1,79 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Approval screens
+//! The `nanos_ui` review and approval screens block in their own event loop, which drops every APDU and ticker event
+//! that arrives while they are shown. These screens wait for the buttons in a loop of their own instead, so that they
+//! keep counting ticker events towards the inactivity timeout.
+//! - An approval left waiting for the inactivity timeout is rejected, see `InactivityTimer`.
+
+use nanos_sdk::{buttons::ButtonEvent, io};
+use nanos_ui::ui;
+
+use crate::{
+    display::{page, page_count, Message},
+    timeout::InactivityTimer,
+};
+
+/// Let the user page through `text` with the left and right buttons until they press both, returning `false` if they
+/// did not before the timeout
+pub fn review(comm: &mut io::Comm, text: &str) -> bool {
+    let last_page = page_count(text) - 1;
+    let mut current = 0;
+    let mut timer = InactivityTimer::new();
+    loop {
+        ui::SingleMessage::new(page(text, current)).show();
+        match wait_for_button(comm, &mut timer) {
+            Some(ButtonEvent::LeftButtonRelease) => current = current.saturating_sub(1),
+            Some(ButtonEvent::RightButtonRelease) => current = (current + 1).min(last_page),
+            Some(ButtonEvent::BothButtonsRelease) => return true,
+            Some(_) => {},
+            None => return false,
+        }
+    }
+}
+
+/// Let the user page through `message` as for `review`, refusing a truncated message without showing it, as the user
+/// would otherwise approve text they never saw in full
+pub fn review_message(comm: &mut io::Comm, message: &Message) -> bool {
+    !message.is_truncated() && review(comm, message.as_str())
+}
+
+/// Ask the user to approve `prompt`, stepping between the prompt, approve and reject with the left and right buttons
+/// and choosing with both, which is a rejection once the timeout passes
+pub fn ask(comm: &mut io::Comm, prompt: &str) -> bool {
+    let choices = [prompt, "Approve", "Reject"];
+    let mut choice = 0;
+    let mut timer = InactivityTimer::new();
+    loop {
+        ui::SingleMessage::new(choices[choice]).show();
+        match wait_for_button(comm, &mut timer) {
+            Some(ButtonEvent::LeftButtonRelease) => choice = (choice + choices.len() - 1) % choices.len(),
+            Some(ButtonEvent::RightButtonRelease) => choice = (choice + 1) % choices.len(),
+            Some(ButtonEvent::BothButtonsRelease) if choice == 1 => return true,
+            Some(ButtonEvent::BothButtonsRelease) if choice == 2 => return false,
+            Some(_) => {},
+            None => return false,
+        }
+    }
+}
+
+// Wait for the next button event, or `None` once `timer` times out
+// - No APDU arrives in the meantime, as the host waits for the reply to the APDU being approved before it sends
+//   another.
+fn wait_for_button(comm: &mut io::Comm, timer: &mut InactivityTimer) -> Option<ButtonEvent> {
+    loop {
+        match comm.next_event::<io::ApduHeader>() {
+            io::Event::Button(button) => {
+                timer.reset();
+                return Some(button);
+            },
+            io::Event::Command(_) => {},
+            io::Event::Ticker => {
+                if timer.tick() {
+                    return None;
+                }
+            },
+        }
+    }
+}
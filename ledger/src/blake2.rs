@@ -1,3 +1,3 @@
 use blake2::{digest::consts::U32, Blake2b};
 
-pub type Blake256 = Blake2b<U32>;
\ No newline at end of file
+pub type Blake256 = Blake2b<U32>;
@@ -0,0 +1,398 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Covenant decoding
+//! A covenant restricts the outputs of the transaction that spends an output. It is borsh encoded as the bytes of its
+//! tokens, prefixed by their length as a varint like a `TariScript`, so an output without a covenant has the encoding
+//! `0x00`.
+//! - The tokens are a single filter in prefix order, as serialized by `tari_core`: each filter is a byte code followed
+//!   by its arguments, then by the filters it combines. Arguments are a byte code and their borsh encoded value: hashes
+//!   are 32 bytes, keys and commitments u32 length prefixed, integers 8 bytes, and scripts and covenants varint length
+//!   prefixed.
+//! - Every filter is decoded, so the user sees what the covenant checks, while hashes, fields and nested scripts are
+//!   only checked to be well formed.
+
+use borsh::BorshDeserialize;
+
+use crate::errors::Error;
+
+/// The largest number of filters a covenant can have and still be reviewed
+pub const MAX_COVENANT_FILTERS: usize = 8;
+
+// The byte codes of the filters
+const FILTER_IDENTITY: u8 = 0x20;
+const FILTER_AND: u8 = 0x21;
+const FILTER_OR: u8 = 0x22;
+const FILTER_XOR: u8 = 0x23;
+const FILTER_NOT: u8 = 0x24;
+const FILTER_OUTPUT_HASH_EQ: u8 = 0x30;
+const FILTER_FIELDS_PRESERVED: u8 = 0x31;
+const FILTER_FIELD_EQ: u8 = 0x32;
+const FILTER_FIELDS_HASHED_EQ: u8 = 0x33;
+const FILTER_ABSOLUTE_HEIGHT: u8 = 0x34;
+
+// The byte codes of the arguments
+const ARG_HASH: u8 = 0x01;
+const ARG_PUBLIC_KEY: u8 = 0x02;
+const ARG_COMMITMENT: u8 = 0x03;
+const ARG_TARI_SCRIPT: u8 = 0x04;
+const ARG_COVENANT: u8 = 0x05;
+const ARG_UINT: u8 = 0x06;
+const ARG_OUTPUT_FIELD: u8 = 0x07;
+const ARG_OUTPUT_FIELDS: u8 = 0x08;
+const ARG_BYTES: u8 = 0x09;
+const ARG_OUTPUT_TYPE: u8 = 0x0a;
+
+// The largest output field, `OutputField::Covenant` being the last
+const MAX_OUTPUT_FIELD: u8 = 0x09;
+
+/// A covenant filter, with the argument the user needs to see
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Identity,
+    And,
+    Or,
+    Xor,
+    Not,
+    OutputHashEq,
+    FieldsPreserved,
+    FieldEq,
+    FieldsHashedEq,
+    /// Only outputs from this height on pass
+    AbsoluteHeight(u64),
+}
+
+impl Filter {
+    /// The name of the filter, as shown to the user
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Identity => "identity",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Xor => "xor",
+            Self::Not => "not",
+            Self::OutputHashEq => "output hash equals",
+            Self::FieldsPreserved => "fields preserved",
+            Self::FieldEq => "field equals",
+            Self::FieldsHashedEq => "fields hashed equal",
+            Self::AbsoluteHeight(_) => "from height",
+        }
+    }
+
+    // Read a filter and its arguments from the front of `buf`, returning it with the number of filters it combines
+    fn read(buf: &mut &[u8]) -> Result<(Self, usize), Error> {
+        Ok(match read::<u8>(buf)? {
+            FILTER_IDENTITY => (Self::Identity, 0),
+            FILTER_AND => (Self::And, 2),
+            FILTER_OR => (Self::Or, 2),
+            FILTER_XOR => (Self::Xor, 2),
+            FILTER_NOT => (Self::Not, 1),
+            FILTER_OUTPUT_HASH_EQ => {
+                read_arg(buf, Some(ARG_HASH))?;
+                (Self::OutputHashEq, 0)
+            },
+            FILTER_FIELDS_PRESERVED => {
+                read_arg(buf, Some(ARG_OUTPUT_FIELDS))?;
+                (Self::FieldsPreserved, 0)
+            },
+            FILTER_FIELD_EQ => {
+                read_arg(buf, Some(ARG_OUTPUT_FIELD))?;
+                read_arg(buf, None)?;
+                (Self::FieldEq, 0)
+            },
+            FILTER_FIELDS_HASHED_EQ => {
+                read_arg(buf, Some(ARG_OUTPUT_FIELDS))?;
+                read_arg(buf, Some(ARG_HASH))?;
+                (Self::FieldsHashedEq, 0)
+            },
+            FILTER_ABSOLUTE_HEIGHT => (Self::AbsoluteHeight(read_arg(buf, Some(ARG_UINT))?), 0),
+            _ => return Err(Error::InvalidCovenant),
+        })
+    }
+}
+
+/// A decoded covenant
+pub struct Covenant {
+    /// The filters in prefix order
+    filters: [Option<Filter>; MAX_COVENANT_FILTERS],
+}
+
+impl Covenant {
+    /// Decode the borsh encoded covenant `bytes`
+    /// - Returns `Error::InvalidCovenant` for an unknown filter or argument, a filter missing an operand, more than
+    ///   `MAX_COVENANT_FILTERS` filters, or if the bytes are cut short or have trailing data.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        let buf = &mut bytes;
+        let length = read_varint(buf)?;
+        if length != buf.len() as u64 {
+            return Err(Error::InvalidCovenant);
+        }
+
+        let mut filters: [Option<Filter>; MAX_COVENANT_FILTERS] = Default::default();
+        // A covenant is a single filter, and each filter read may call for more
+        let mut pending = if buf.is_empty() { 0 } else { 1 };
+        let mut count = 0;
+        while pending > 0 {
+            let (filter, operands) = Filter::read(buf)?;
+            *filters.get_mut(count).ok_or(Error::InvalidCovenant)? = Some(filter);
+            count += 1;
+            pending = pending - 1 + operands;
+        }
+        if !buf.is_empty() {
+            return Err(Error::InvalidCovenant);
+        }
+        Ok(Self { filters })
+    }
+
+    /// The filters, in prefix order
+    pub fn filters(&self) -> impl Iterator<Item = Filter> + '_ {
+        self.filters.iter().flatten().copied()
+    }
+
+    /// Whether the covenant places no restriction on the output
+    pub fn is_empty(&self) -> bool {
+        self.filters[0].is_none()
+    }
+}
+
+// Read an argument, which must be of the type `expected` if given, returning its value if it is an integer
+fn read_arg(buf: &mut &[u8], expected: Option<u8>) -> Result<u64, Error> {
+    let arg = read::<u8>(buf)?;
+    if expected.map_or(false, |expected| expected != arg) {
+        return Err(Error::InvalidCovenant);
+    }
+    match arg {
+        ARG_HASH => skip(buf, 32)?,
+        ARG_PUBLIC_KEY | ARG_COMMITMENT => {
+            if read::<u32>(buf)? != 32 {
+                return Err(Error::InvalidCovenant);
+            }
+            skip(buf, 32)?;
+        },
+        ARG_TARI_SCRIPT | ARG_COVENANT => {
+            let length = read_varint(buf)?;
+            skip(buf, usize::try_from(length).map_err(|_| Error::InvalidCovenant)?)?;
+        },
+        ARG_BYTES => {
+            let length = read::<u32>(buf)?;
+            skip(buf, length as usize)?;
+        },
+        ARG_UINT => return read(buf),
+        ARG_OUTPUT_FIELD => read_output_field(buf)?,
+        ARG_OUTPUT_FIELDS => {
+            for _ in 0..read::<u32>(buf)? {
+                read_output_field(buf)?;
+            }
+        },
+        ARG_OUTPUT_TYPE => {
+            if read::<u8>(buf)? > 4 {
+                return Err(Error::InvalidCovenant);
+            }
+        },
+        _ => return Err(Error::InvalidCovenant),
+    }
+    Ok(0)
+}
+
+// Read the byte code of an output field
+fn read_output_field(buf: &mut &[u8]) -> Result<(), Error> {
+    if read::<u8>(buf)? > MAX_OUTPUT_FIELD {
+        return Err(Error::InvalidCovenant);
+    }
+    Ok(())
+}
+
+// Read an unsigned LEB128 varint, as `tari_core` writes the lengths of scripts and covenants
+fn read_varint(buf: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read::<u8>(buf)?;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::InvalidCovenant)
+}
+
+// Skip `length` bytes
+fn skip(buf: &mut &[u8], length: usize) -> Result<(), Error> {
+    if length > buf.len() {
+        return Err(Error::InvalidCovenant);
+    }
+    *buf = &buf[length..];
+    Ok(())
+}
+
+// Read a borsh encoded value from the front of `buf`
+fn read<T: BorshDeserialize>(buf: &mut &[u8]) -> Result<T, Error> {
+    T::deserialize(buf).map_err(|_| Error::InvalidCovenant)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    // The borsh encoding of a covenant with the tokens `tokens`, as `tari_core` writes it
+    fn covenant(tokens: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut length = tokens.len();
+        while length >= 0x80 {
+            buf.push(length as u8 | 0x80);
+            length >>= 7;
+        }
+        buf.push(length as u8);
+        buf.extend_from_slice(tokens);
+        buf
+    }
+
+    fn filters(tokens: &[u8]) -> Result<Vec<Filter>, Error> {
+        Ok(Covenant::from_bytes(&covenant(tokens))?.filters().collect())
+    }
+
+    // The tokens of `field_eq(field, arg)` for an argument of every type
+    fn field_eq_tokens() -> Vec<Vec<u8>> {
+        let mut public_key = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 2, ARG_PUBLIC_KEY, 32, 0, 0, 0]);
+        public_key.extend_from_slice(&[0x11; 32]);
+        let mut commitment = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 0, ARG_COMMITMENT, 32, 0, 0, 0]);
+        commitment.extend_from_slice(&[0x22; 32]);
+        let mut hash = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 0, ARG_HASH]);
+        hash.extend_from_slice(&[0x33; 32]);
+        let script = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 1, ARG_TARI_SCRIPT, 1, 0x73]);
+        let nested = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 3, ARG_COVENANT, 1, FILTER_IDENTITY]);
+        let mut uint = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 6, ARG_UINT]);
+        uint.extend_from_slice(&1000u64.to_le_bytes());
+        let bytes = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 4, ARG_BYTES, 2, 0, 0, 0, 0xab, 0xcd]);
+        let output_type = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 5, ARG_OUTPUT_TYPE, 2]);
+        Vec::from([public_key, commitment, hash, script, nested, uint, bytes, output_type])
+    }
+
+    // Every vector must be rejected once cut short by any number of bytes, with or without its length prefix fixed up,
+    // or with a trailing byte
+    fn rejects_truncated_and_oversized(tokens: &[u8]) -> bool {
+        let bytes = covenant(tokens);
+        let mut oversized = Vec::from(tokens);
+        oversized.push(FILTER_IDENTITY);
+        (0..bytes.len()).all(|length| Covenant::from_bytes(&bytes[..length]).is_err()) &&
+            (1..tokens.len()).all(|length| filters(&tokens[..length]).is_err()) &&
+            filters(&oversized).is_err()
+    }
+
+    #[test]
+    fn empty_covenant() {
+        let covenant = Covenant::from_bytes(&[0]).unwrap();
+        assert_eq!(covenant.is_empty(), true);
+        assert_eq!(covenant.filters().count(), 0);
+        // A u32 length prefix is not a covenant
+        assert_eq!(Covenant::from_bytes(&[0; 4]).is_err(), true);
+        assert_eq!(Covenant::from_bytes(&[]).is_err(), true);
+    }
+
+    #[test]
+    fn combining_filters() {
+        for (code, filter) in [
+            (FILTER_AND, Filter::And),
+            (FILTER_OR, Filter::Or),
+            (FILTER_XOR, Filter::Xor),
+        ] {
+            let tokens = [code, FILTER_IDENTITY, FILTER_IDENTITY];
+            assert_eq!(filters(&tokens).unwrap(), [filter, Filter::Identity, Filter::Identity]);
+            assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+        }
+        let tokens = [FILTER_NOT, FILTER_IDENTITY];
+        assert_eq!(filters(&tokens).unwrap(), [Filter::Not, Filter::Identity]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+        assert_eq!(filters(&[FILTER_IDENTITY]).unwrap(), [Filter::Identity]);
+    }
+
+    #[test]
+    fn output_filters() {
+        let mut tokens = Vec::from([FILTER_OUTPUT_HASH_EQ, ARG_HASH]);
+        tokens.extend_from_slice(&[0x44; 32]);
+        assert_eq!(filters(&tokens).unwrap(), [Filter::OutputHashEq]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+
+        let tokens = [FILTER_FIELDS_PRESERVED, ARG_OUTPUT_FIELDS, 2, 0, 0, 0, 1, 4];
+        assert_eq!(filters(&tokens).unwrap(), [Filter::FieldsPreserved]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+
+        let mut tokens = Vec::from([FILTER_FIELDS_HASHED_EQ, ARG_OUTPUT_FIELDS, 1, 0, 0, 0, 9, ARG_HASH]);
+        tokens.extend_from_slice(&[0x55; 32]);
+        assert_eq!(filters(&tokens).unwrap(), [Filter::FieldsHashedEq]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+
+        let mut tokens = Vec::from([FILTER_ABSOLUTE_HEIGHT, ARG_UINT]);
+        tokens.extend_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(filters(&tokens).unwrap(), [Filter::AbsoluteHeight(1000)]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+
+        for tokens in field_eq_tokens() {
+            assert_eq!(filters(&tokens).unwrap(), [Filter::FieldEq]);
+            assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+        }
+    }
+
+    #[test]
+    fn nested_filters() {
+        // `and(fields_preserved([script]), absolute_height(1000))`
+        let mut tokens = Vec::from([FILTER_AND, FILTER_FIELDS_PRESERVED, ARG_OUTPUT_FIELDS, 1, 0, 0, 0, 1]);
+        tokens.extend_from_slice(&[FILTER_ABSOLUTE_HEIGHT, ARG_UINT]);
+        tokens.extend_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(filters(&tokens).unwrap(), [
+            Filter::And,
+            Filter::FieldsPreserved,
+            Filter::AbsoluteHeight(1000)
+        ]);
+        assert_eq!(rejects_truncated_and_oversized(&tokens), true);
+
+        // At most `MAX_COVENANT_FILTERS` filters can be reviewed
+        let mut tokens = Vec::from([FILTER_NOT; MAX_COVENANT_FILTERS]);
+        tokens[MAX_COVENANT_FILTERS - 1] = FILTER_IDENTITY;
+        assert_eq!(filters(&tokens).unwrap().len(), MAX_COVENANT_FILTERS);
+        tokens.insert(0, FILTER_NOT);
+        assert_eq!(filters(&tokens).is_err(), true);
+    }
+
+    #[test]
+    fn invalid_covenants() {
+        // Unknown filters and arguments, and arguments of the wrong type
+        assert_eq!(filters(&[0x25]).is_err(), true);
+        assert_eq!(filters(&[FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 0, 0x0b]).is_err(), true);
+        assert_eq!(filters(&[FILTER_ABSOLUTE_HEIGHT, ARG_HASH]).is_err(), true);
+
+        // An output field or output type out of range
+        assert_eq!(
+            filters(&[
+                FILTER_FIELDS_PRESERVED,
+                ARG_OUTPUT_FIELDS,
+                1,
+                0,
+                0,
+                0,
+                MAX_OUTPUT_FIELD + 1
+            ])
+            .is_err(),
+            true
+        );
+        assert_eq!(
+            filters(&[FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 5, ARG_OUTPUT_TYPE, 5]).is_err(),
+            true
+        );
+
+        // A public key that is not 32 bytes
+        let mut tokens = Vec::from([FILTER_FIELD_EQ, ARG_OUTPUT_FIELD, 2, ARG_PUBLIC_KEY, 33, 0, 0, 0]);
+        tokens.extend_from_slice(&[0x11; 33]);
+        assert_eq!(filters(&tokens).is_err(), true);
+
+        // A length prefix that claims more tokens than there are, or a varint that does not end
+        let mut bytes = covenant(&[FILTER_IDENTITY]);
+        bytes[0] = 2;
+        assert_eq!(Covenant::from_bytes(&bytes).is_err(), true);
+        assert_eq!(Covenant::from_bytes(&[0xff; 11]).is_err(), true);
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Hardware accelerated Ristretto scalar multiplication
+//! Ristretto points are cosets of ed25519 points, so a scalar multiplication can be done by the BOLOS `cx` ed25519
+//! implementation on any representative of the coset, after which the result is Ristretto-encoded again. The
+//! encoding and decoding follow [RFC 9496](https://www.rfc-editor.org/rfc/rfc9496#section-4.3) and use the `cx` modular
+//! arithmetic syscalls, with field elements held as 32 byte big-endian integers as the syscalls expect.
+//! - Points are taken and returned as Ristretto encodings, and sums are taken on the ed25519 representatives, so that
+//!   no software decompression or compression is needed around the syscalls.
+//! - Each function returns `None` if a syscall fails, so that the caller can fall back to the software implementation.
+
+use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
+use nanos_sdk::{
+    bindings::{
+        cx_curve_t,
+        cx_ecfp_add_point_no_throw,
+        cx_ecfp_scalar_mult_no_throw,
+        cx_math_addm_no_throw,
+        cx_math_multm_no_throw,
+        cx_math_powm_no_throw,
+        cx_math_subm_no_throw,
+    },
+    ecc::CurvesId,
+};
+
+/// A field element modulo `p = 2^255 - 19`, as a big-endian integer
+type Fe = [u8; 32];
+
+/// The length of an uncompressed ed25519 point, `04 || x || y`
+const POINT_LENGTH: usize = 65;
+
+/// The field modulus `p = 2^255 - 19`
+const P: Fe = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xed,
+];
+/// The exponent `(p - 5) / 8` used for the inverse square root
+const P_MINUS_5_DIV_8: Fe = [
+    0x0f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfd,
+];
+/// The Edwards curve constant `d = -121665 / 121666`
+const D: Fe = [
+    0x52, 0x03, 0x6c, 0xee, 0x2b, 0x6f, 0xfe, 0x73, 0x8c, 0xc7, 0x40, 0x79, 0x77, 0x79, 0xe8, 0x98, 0x00, 0x70, 0x0a,
+    0x4d, 0x41, 0x41, 0xd8, 0xab, 0x75, 0xeb, 0x4d, 0xca, 0x13, 0x59, 0x78, 0xa3,
+];
+/// `sqrt(-1)`
+const SQRT_M1: Fe = [
+    0x2b, 0x83, 0x24, 0x80, 0x4f, 0xc1, 0xdf, 0x0b, 0x2b, 0x4d, 0x00, 0x99, 0x3d, 0xfb, 0xd7, 0xa7, 0x2f, 0x43, 0x18,
+    0x06, 0xad, 0x2f, 0xe4, 0x78, 0xc4, 0xee, 0x1b, 0x27, 0x4a, 0x0e, 0xa0, 0xb0,
+];
+/// `1 / sqrt(a - d)`, with `a = -1`
+const INVSQRT_A_MINUS_D: Fe = [
+    0x78, 0x6c, 0x89, 0x05, 0xcf, 0xaf, 0xfc, 0xa2, 0x16, 0xc2, 0x7b, 0x91, 0xfe, 0x01, 0xd8, 0x40, 0x9d, 0x2f, 0x16,
+    0x17, 0x5a, 0x41, 0x72, 0xbe, 0x99, 0xc8, 0xfd, 0xaa, 0x80, 0x5d, 0x40, 0xea,
+];
+/// The ed25519 base point, which is also the representative of the Ristretto base point
+const BASE_POINT: [u8; POINT_LENGTH] = [
+    0x04, 0x21, 0x69, 0x36, 0xd3, 0xcd, 0x6e, 0x53, 0xfe, 0xc0, 0xa4, 0xe2, 0x31, 0xfd, 0xd6, 0xdc, 0x5c, 0x69, 0x2c,
+    0xc7, 0x60, 0x95, 0x25, 0xa7, 0xb2, 0xc9, 0x56, 0x2d, 0x60, 0x8f, 0x25, 0xd5, 0x1a, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x58,
+];
+
+const ZERO: Fe = [0u8; 32];
+const ONE: Fe = {
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    one
+};
+
+/// Compute `k.G + k_1.P_1 + k_2.P_2 + ...` for the Ristretto base point `G` and the encoded points `P_i`
+pub fn mul_base_and_add(k: &Scalar, terms: &[(&Scalar, &CompressedRistretto)]) -> Option<CompressedRistretto> {
+    let mut sum = BASE_POINT;
+    scalar_mult(&mut sum, k)?;
+    for (k_i, p_i) in terms {
+        let mut point = decode(p_i)?;
+        scalar_mult(&mut point, k_i)?;
+        add_point(&mut sum, &point)?;
+    }
+    encode(&sum)
+}
+
+/// Multiply the encoded Ristretto point `p` by `k`
+pub fn mul(k: &Scalar, p: &CompressedRistretto) -> Option<CompressedRistretto> {
+    let mut point = decode(p)?;
+    scalar_mult(&mut point, k)?;
+    encode(&point)
+}
+
+// Multiply the uncompressed ed25519 point in place by `k`, which the syscall expects as a big-endian integer
+fn scalar_mult(point: &mut [u8; POINT_LENGTH], k: &Scalar) -> Option<()> {
+    let mut k_be = k.to_bytes();
+    k_be.reverse();
+    let err = unsafe {
+        cx_ecfp_scalar_mult_no_throw(
+            CurvesId::Ed25519 as cx_curve_t,
+            point.as_mut_ptr(),
+            k_be.as_ptr(),
+            k_be.len(),
+        )
+    };
+    (err == 0).then_some(())
+}
+
+// Add the uncompressed ed25519 point `q` to `sum` in place
+fn add_point(sum: &mut [u8; POINT_LENGTH], q: &[u8; POINT_LENGTH]) -> Option<()> {
+    let p = *sum;
+    let err = unsafe {
+        cx_ecfp_add_point_no_throw(
+            CurvesId::Ed25519 as cx_curve_t,
+            sum.as_mut_ptr(),
+            p.as_ptr(),
+            q.as_ptr(),
+        )
+    };
+    (err == 0).then_some(())
+}
+
+// Decode a Ristretto encoding to a representative ed25519 point `04 || x || y`
+fn decode(compressed: &CompressedRistretto) -> Option<[u8; POINT_LENGTH]> {
+    let mut s = compressed.to_bytes();
+    s.reverse();
+    if s >= P || is_negative(&s) {
+        return None;
+    }
+    let ss = mul_fe(&s, &s)?;
+    let u1 = sub_fe(&ONE, &ss)?;
+    let u2 = add_fe(&ONE, &ss)?;
+    let u2_sqr = mul_fe(&u2, &u2)?;
+    // v = -(d.u1^2) - u2^2
+    let d_u1_sqr = mul_fe(&D, &mul_fe(&u1, &u1)?)?;
+    let v = sub_fe(&sub_fe(&ZERO, &d_u1_sqr)?, &u2_sqr)?;
+    let (was_square, invsqrt) = sqrt_ratio_m1(&ONE, &mul_fe(&v, &u2_sqr)?)?;
+    let den_x = mul_fe(&invsqrt, &u2)?;
+    let den_y = mul_fe(&mul_fe(&invsqrt, &den_x)?, &v)?;
+    let two_s = add_fe(&s, &s)?;
+    let x = abs_fe(&mul_fe(&two_s, &den_x)?)?;
+    let y = mul_fe(&u1, &den_y)?;
+    let t = mul_fe(&x, &y)?;
+    if !was_square || is_negative(&t) || y == ZERO {
+        return None;
+    }
+    let mut point = [0u8; POINT_LENGTH];
+    point[0] = 0x04;
+    point[1..33].copy_from_slice(&x);
+    point[33..].copy_from_slice(&y);
+    Some(point)
+}
+
+// Ristretto-encode the ed25519 point `04 || x || y`, taking `z = 1` and `t = x.y`
+fn encode(point: &[u8; POINT_LENGTH]) -> Option<CompressedRistretto> {
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&point[1..33]);
+    let mut y = [0u8; 32];
+    y.copy_from_slice(&point[33..]);
+    let t = mul_fe(&x, &y)?;
+
+    let u1 = mul_fe(&add_fe(&ONE, &y)?, &sub_fe(&ONE, &y)?)?;
+    let u2 = mul_fe(&x, &y)?;
+    let (_, invsqrt) = sqrt_ratio_m1(&ONE, &mul_fe(&u1, &mul_fe(&u2, &u2)?)?)?;
+    let den1 = mul_fe(&invsqrt, &u1)?;
+    let den2 = mul_fe(&invsqrt, &u2)?;
+    let z_inv = mul_fe(&mul_fe(&den1, &den2)?, &t)?;
+    let rotate = is_negative(&mul_fe(&t, &z_inv)?);
+    let (x, y, den_inv) = if rotate {
+        let ix = mul_fe(&x, &SQRT_M1)?;
+        let iy = mul_fe(&y, &SQRT_M1)?;
+        (iy, ix, mul_fe(&den1, &INVSQRT_A_MINUS_D)?)
+    } else {
+        (x, y, den2)
+    };
+    let y = if is_negative(&mul_fe(&x, &z_inv)?) {
+        sub_fe(&ZERO, &y)?
+    } else {
+        y
+    };
+    let mut s = abs_fe(&mul_fe(&den_inv, &sub_fe(&ONE, &y)?)?)?;
+    s.reverse();
+    Some(CompressedRistretto(s))
+}
+
+// Compute `sqrt(u / v)` if it exists, or `sqrt(i.u / v)` otherwise, returning whether `u / v` was square
+fn sqrt_ratio_m1(u: &Fe, v: &Fe) -> Option<(bool, Fe)> {
+    let v2 = mul_fe(v, v)?;
+    let v3 = mul_fe(&v2, v)?;
+    let v7 = mul_fe(&mul_fe(&v3, &v3)?, v)?;
+    let uv3 = mul_fe(u, &v3)?;
+    let uv7 = mul_fe(u, &v7)?;
+    let r = mul_fe(&uv3, &pow_fe(&uv7, &P_MINUS_5_DIV_8)?)?;
+    let check = mul_fe(v, &mul_fe(&r, &r)?)?;
+
+    let minus_u = sub_fe(&ZERO, u)?;
+    let correct_sign_sqrt = check == *u;
+    let flipped_sign_sqrt = check == minus_u;
+    let flipped_sign_sqrt_i = check == mul_fe(&minus_u, &SQRT_M1)?;
+    let r = if flipped_sign_sqrt || flipped_sign_sqrt_i {
+        mul_fe(&r, &SQRT_M1)?
+    } else {
+        r
+    };
+    Some((correct_sign_sqrt || flipped_sign_sqrt, abs_fe(&r)?))
+}
+
+// A field element is negative if its least significant bit is set
+fn is_negative(a: &Fe) -> bool {
+    a[31] & 1 == 1
+}
+
+fn abs_fe(a: &Fe) -> Option<Fe> {
+    if is_negative(a) {
+        sub_fe(&ZERO, a)
+    } else {
+        Some(*a)
+    }
+}
+
+fn add_fe(a: &Fe, b: &Fe) -> Option<Fe> {
+    let mut r = ZERO;
+    let err = unsafe { cx_math_addm_no_throw(r.as_mut_ptr(), a.as_ptr(), b.as_ptr(), P.as_ptr(), r.len()) };
+    (err == 0).then_some(r)
+}
+
+fn sub_fe(a: &Fe, b: &Fe) -> Option<Fe> {
+    let mut r = ZERO;
+    let err = unsafe { cx_math_subm_no_throw(r.as_mut_ptr(), a.as_ptr(), b.as_ptr(), P.as_ptr(), r.len()) };
+    (err == 0).then_some(r)
+}
+
+fn mul_fe(a: &Fe, b: &Fe) -> Option<Fe> {
+    let mut r = ZERO;
+    let err = unsafe { cx_math_multm_no_throw(r.as_mut_ptr(), a.as_ptr(), b.as_ptr(), P.as_ptr(), r.len()) };
+    (err == 0).then_some(r)
+}
+
+fn pow_fe(a: &Fe, e: &Fe) -> Option<Fe> {
+    let mut r = ZERO;
+    let err = unsafe { cx_math_powm_no_throw(r.as_mut_ptr(), a.as_ptr(), e.as_ptr(), e.len(), P.as_ptr(), r.len()) };
+    (err == 0).then_some(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use curve25519_dalek::{constants::RISTRETTO_BASEPOINT_TABLE, ristretto::RistrettoPoint, traits::Identity};
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+    use crate::ristretto_keys::RistrettoSecretKey;
+
+    // Scalars with a special meaning for the syscalls, and a few random ones
+    fn scalars() -> [Scalar; 5] {
+        [
+            Scalar::ZERO,
+            Scalar::ONE,
+            RistrettoSecretKey::random().0,
+            RistrettoSecretKey::random().0,
+            RistrettoSecretKey::random().0,
+        ]
+    }
+
+    // The identity, the base point and a random point
+    fn points() -> [RistrettoPoint; 3] {
+        [
+            RistrettoPoint::identity(),
+            RISTRETTO_BASEPOINT_TABLE.basepoint(),
+            &RistrettoSecretKey::random().0 * RISTRETTO_BASEPOINT_TABLE,
+        ]
+    }
+
+    // A syscall may refuse a zero scalar or the identity, which `ristretto_keys` then computes in software, but any
+    // result it does return must match the software one
+    fn check(result: Option<CompressedRistretto>, expected: RistrettoPoint, must_succeed: bool) {
+        match result {
+            Some(compressed) => assert_eq!(compressed.as_bytes(), expected.compress().as_bytes()),
+            None => assert_eq!(must_succeed, false),
+        }
+    }
+
+    #[test]
+    fn mul_matches_dalek() {
+        for p in points() {
+            for k in scalars() {
+                let must_succeed = k != Scalar::ZERO && p != RistrettoPoint::identity();
+                check(mul(&k, &p.compress()), k * p, must_succeed);
+            }
+        }
+    }
+
+    #[test]
+    fn mul_base_and_add_matches_dalek() {
+        let [k, k_1, k_2, ..] = scalars();
+        for p in points() {
+            let q = &RistrettoSecretKey::random().0 * RISTRETTO_BASEPOINT_TABLE;
+            for (a, b) in [(k, k_1), (k_1, k_2), (k_2, k)] {
+                let expected = &a * RISTRETTO_BASEPOINT_TABLE + b * p + k_2 * q;
+                let terms = [(&b, &p.compress()), (&k_2, &q.compress())];
+                check(mul_base_and_add(&a, &terms), expected, false);
+            }
+            check(mul_base_and_add(&k_2, &[]), &k_2 * RISTRETTO_BASEPOINT_TABLE, true);
+        }
+    }
+
+    #[test]
+    fn decodes_like_dalek() {
+        let mut p_le = P;
+        p_le.reverse();
+        // The identity, the base point, `p` itself, a negative `s`, an `s` of `p` or more, and one that is not a point
+        let encodings = [
+            [0u8; 32],
+            RISTRETTO_BASEPOINT_TABLE.basepoint().compress().to_bytes(),
+            p_le,
+            {
+                let mut s = [0u8; 32];
+                s[0] = 1;
+                s
+            },
+            [0xff; 32],
+            [0x42; 32],
+        ];
+        for bytes in encodings {
+            let compressed = CompressedRistretto(bytes);
+            let decoded = decode(&compressed);
+            assert_eq!(decoded.is_some(), compressed.decompress().is_some());
+            // A canonical encoding is the only one of its point
+            if let Some(point) = decoded {
+                assert_eq!(encode(&point).map(|c| c.to_bytes()), Some(bytes));
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Screen text
+//! Messages for the screens are built in a fixed buffer on the stack, so that showing them can never exhaust the heap.
+//! Keys are written out in full as hex, and `ui::MessageScroller` pages through them.
+
+use crate::format::{AmountFormat, XTR};
+
+/// The characters that fit on a page of the screen, as for `ui::MessageScroller`
+pub const CHARACTERS_PER_PAGE: usize = 128 / 7;
+
+/// The number of pages `text` is shown on, which is at least one
+pub fn page_count(text: &str) -> usize {
+    ((text.len() + CHARACTERS_PER_PAGE - 1) / CHARACTERS_PER_PAGE).max(1)
+}
+
+/// The text shown on page `page` of `text`
+pub fn page(text: &str, page: usize) -> &str {
+    let start = (page * CHARACTERS_PER_PAGE).min(text.len());
+    let end = (start + CHARACTERS_PER_PAGE).min(text.len());
+    text.get(start..end).unwrap_or_default()
+}
+
+/// The longest message a screen is built from, enough for a payment with the largest amount to a key in hex
+pub const MAX_MESSAGE_LENGTH: usize = 128;
+
+// Plain decimal numbers, such as indices and heights
+const DECIMAL: AmountFormat = AmountFormat {
+    decimals: 0,
+    thousands_separator: None,
+    unit: "",
+};
+
+/// A message for the screens, held on the stack
+/// - Text that does not fit is dropped, so a message is never more than `MAX_MESSAGE_LENGTH` bytes, and the message is
+///   marked as truncated, see `is_truncated`.
+pub struct Message {
+    bytes: [u8; MAX_MESSAGE_LENGTH],
+    length: usize,
+    truncated: bool,
+}
+
+impl Message {
+    /// Start a message with `text`
+    pub fn new(text: &str) -> Self {
+        let mut message = Self {
+            bytes: [0u8; MAX_MESSAGE_LENGTH],
+            length: 0,
+            truncated: false,
+        };
+        message.push_str(text);
+        message
+    }
+
+    /// Append `text`
+    pub fn push_str(&mut self, text: &str) -> &mut Self {
+        for byte in text.bytes() {
+            self.push(byte);
+        }
+        self
+    }
+
+    /// Append `number` in decimal
+    pub fn push_u64(&mut self, number: u64) -> &mut Self {
+        self.push_str(DECIMAL.format(number).as_str())
+    }
+
+    /// Append `bytes` in lowercase hex
+    pub fn push_hex(&mut self, bytes: &[u8]) -> &mut Self {
+        const HEX_CHARS: [u8; 16] = *b"0123456789abcdef";
+        for byte in bytes {
+            self.push(HEX_CHARS[(byte >> 4) as usize]);
+            self.push(HEX_CHARS[(byte & 0x0F) as usize]);
+        }
+        self
+    }
+
+    /// Append an amount in micro Tari, formatted as XTR
+    pub fn push_amount(&mut self, micro_tari: u64) -> &mut Self {
+        self.push_str(XTR.format(micro_tari).as_str())
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or_default()
+    }
+
+    /// Whether text was dropped because it did not fit, so that the message no longer says all it was meant to
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.length < MAX_MESSAGE_LENGTH {
+            self.bytes[self.length] = byte;
+            self.length += 1;
+        } else {
+            self.truncated = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    #[test]
+    fn builds_messages() {
+        let mut message = Message::new("Output ");
+        message
+            .push_u64(0)
+            .push_str(" ")
+            .push_u64(u64::MAX)
+            .push_str(" ")
+            .push_hex(&[0x0f, 0xa0]);
+        assert_eq!(message.as_str(), "Output 0 18446744073709551615 0fa0");
+        assert_eq!(message.is_truncated(), false);
+    }
+
+    #[test]
+    fn marks_truncated_messages() {
+        let mut message = Message::new("");
+        message.push_hex(&[0u8; MAX_MESSAGE_LENGTH / 2]);
+        assert_eq!(message.as_str().len(), MAX_MESSAGE_LENGTH);
+        assert_eq!(message.is_truncated(), false);
+        message.push_str("");
+        assert_eq!(message.is_truncated(), false);
+        message.push_str("!");
+        assert_eq!(message.as_str().len(), MAX_MESSAGE_LENGTH);
+        assert_eq!(message.is_truncated(), true);
+    }
+}
@@ -0,0 +1,124 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Encrypted value and mask data for Tari outputs
+//! Every Tari output carries its value and commitment mask encrypted with XChaCha20-Poly1305, so that the owner can
+//! recognise and recover the output from the blockchain alone. The AEAD key is derived from an encryption key and the
+//! output's commitment, and the encrypted data is laid out as `tag || nonce || value || mask`, as in `tari_core`'s
+//! `EncryptedData`.
+
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    Key,
+    Tag,
+    XChaCha20Poly1305,
+    XNonce,
+};
+use nanos_sdk::random::LedgerRng;
+use rand_core::RngCore;
+
+use crate::{
+    blake2::Blake256,
+    errors::Error,
+    hashing::DomainSeparatedHasher,
+    pedersen::PedersenCommitment,
+    ristretto_keys::RistrettoSecretKey,
+};
+
+// Define the hashing domain for the AEAD key derivation
+hash_domain!(
+    TransactionSecureNonceKdfDomain,
+    "com.tari.base_layer.core.transactions.secure_nonce_kdf",
+    0
+);
+
+// Additional authenticated data bound to every encryption
+const ENCRYPTED_DATA_AAD: &[u8] = b"TARI_AAD_VALUE_AND_MASK_EXTEND_NONCE_VARIANT";
+
+const SIZE_NONCE: usize = 24;
+const SIZE_VALUE: usize = 8;
+const SIZE_MASK: usize = 32;
+const SIZE_TAG: usize = 16;
+
+/// The length of byte encoded encrypted data
+pub const ENCRYPTED_DATA_LENGTH: usize = SIZE_TAG + SIZE_NONCE + SIZE_VALUE + SIZE_MASK;
+
+/// The encrypted value and mask of an output
+pub struct EncryptedData {
+    data: [u8; ENCRYPTED_DATA_LENGTH],
+}
+
+impl EncryptedData {
+    /// Encrypt the value and mask of the output with commitment `commitment`, using a random nonce
+    pub fn encrypt_data(
+        encryption_key: &RistrettoSecretKey,
+        commitment: &PedersenCommitment,
+        value: u64,
+        mask: &RistrettoSecretKey,
+    ) -> Result<Self, Error> {
+        let mut bytes = [0u8; SIZE_VALUE + SIZE_MASK];
+        bytes[..SIZE_VALUE].copy_from_slice(&value.to_le_bytes());
+        bytes[SIZE_VALUE..].copy_from_slice(mask.as_bytes());
+
+        let mut nonce = [0u8; SIZE_NONCE];
+        LedgerRng.fill_bytes(&mut nonce);
+
+        let cipher = XChaCha20Poly1305::new(&kdf_aead(encryption_key, commitment));
+        let tag = cipher
+            .encrypt_in_place_detached(XNonce::from_slice(&nonce), ENCRYPTED_DATA_AAD, &mut bytes)
+            .map_err(|_| Error::ConversionError)?;
+
+        let mut data = [0u8; ENCRYPTED_DATA_LENGTH];
+        data[..SIZE_TAG].copy_from_slice(&tag);
+        data[SIZE_TAG..SIZE_TAG + SIZE_NONCE].copy_from_slice(&nonce);
+        data[SIZE_TAG + SIZE_NONCE..].copy_from_slice(&bytes);
+        Ok(Self { data })
+    }
+
+    /// Authenticate and decrypt the value and mask of the output with commitment `commitment`
+    /// - Returns `Error::DecryptFailed` if the data was not encrypted with this key for this commitment, or was
+    ///   altered.
+    pub fn decrypt_data(
+        &self,
+        encryption_key: &RistrettoSecretKey,
+        commitment: &PedersenCommitment,
+    ) -> Result<(u64, RistrettoSecretKey), Error> {
+        let tag = Tag::from_slice(&self.data[..SIZE_TAG]);
+        let nonce = XNonce::from_slice(&self.data[SIZE_TAG..SIZE_TAG + SIZE_NONCE]);
+        let mut bytes = [0u8; SIZE_VALUE + SIZE_MASK];
+        bytes.copy_from_slice(&self.data[SIZE_TAG + SIZE_NONCE..]);
+
+        let cipher = XChaCha20Poly1305::new(&kdf_aead(encryption_key, commitment));
+        cipher
+            .decrypt_in_place_detached(nonce, ENCRYPTED_DATA_AAD, &mut bytes, tag)
+            .map_err(|_| Error::DecryptFailed)?;
+
+        let mut value_bytes = [0u8; SIZE_VALUE];
+        value_bytes.copy_from_slice(&bytes[..SIZE_VALUE]);
+        let mask = RistrettoSecretKey::from_bytes(&bytes[SIZE_VALUE..])?;
+        Ok((u64::from_le_bytes(value_bytes), mask))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != ENCRYPTED_DATA_LENGTH {
+            return Err(Error::IncorrectByteLength);
+        }
+        let mut data = [0u8; ENCRYPTED_DATA_LENGTH];
+        data.copy_from_slice(bytes);
+        Ok(Self { data })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+// Derive the AEAD key from the encryption key and the commitment it protects
+fn kdf_aead(encryption_key: &RistrettoSecretKey, commitment: &PedersenCommitment) -> Key {
+    let hash =
+        DomainSeparatedHasher::<Blake256, TransactionSecureNonceKdfDomain>::new_with_label("encrypted_value_and_mask")
+            .chain(encryption_key.as_bytes())
+            .chain(commitment.as_bytes())
+            .finalize();
+    *Key::from_slice(hash.as_ref())
+}
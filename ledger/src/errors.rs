@@ -5,15 +5,71 @@ pub enum Error {
     InvalidChallenge,
     ConversionError,
     DecryptFailed,
+    AdaptorMismatch,
+    SignatureVerificationFailed,
+    CommitmentMismatch,
+    TransactionNotBalanced,
+    TransactionNotStarted,
+    InvalidExtensionDegree,
+    KeyMismatch,
+    InvalidAddress,
+    ScanNotStarted,
+    UnknownLabel,
+    InvalidScript,
+    InvalidOutputFeatures,
+    InvalidCovenant,
+    TransactionRejected,
+    TooManyRecipients,
+    ChangeVerificationFailed,
+    NetworkMismatch,
+    BlindSigningDisabled,
+    EmptyTransaction,
+    ZeroKey,
+    MetadataNotBound,
+    TooManyOutputs,
+    TransactionNotApproved,
+    TransactionApproved,
+    TooManyInputs,
+    UnknownInput,
+    MetadataAlreadyBound,
+    InvalidDerivationPath,
 }
 
-impl Into<Reply> for Error {
-    fn into(self) -> Reply {
-        match self {
+impl From<Error> for Reply {
+    fn from(error: Error) -> Reply {
+        match error {
             Error::IncorrectByteLength => Reply(0x69f0_u16),
             Error::InvalidChallenge => Reply(0x9210_u16),
             Error::ConversionError => Reply(0x6a88_u16),
             Error::DecryptFailed => Reply(0x9d60_u16),
+            Error::AdaptorMismatch => Reply(0x9220_u16),
+            Error::SignatureVerificationFailed => Reply(0x9230_u16),
+            Error::CommitmentMismatch => Reply(0x9240_u16),
+            Error::TransactionNotBalanced => Reply(0x9250_u16),
+            Error::TransactionNotStarted => Reply(0x9260_u16),
+            Error::InvalidExtensionDegree => Reply(0x9270_u16),
+            Error::KeyMismatch => Reply(0x9280_u16),
+            Error::InvalidAddress => Reply(0x9290_u16),
+            Error::ScanNotStarted => Reply(0x92a0_u16),
+            Error::UnknownLabel => Reply(0x92b0_u16),
+            Error::InvalidScript => Reply(0x92c0_u16),
+            Error::InvalidOutputFeatures => Reply(0x92d0_u16),
+            Error::InvalidCovenant => Reply(0x92e0_u16),
+            Error::TransactionRejected => Reply(0x92f0_u16),
+            Error::TooManyRecipients => Reply(0x9300_u16),
+            Error::ChangeVerificationFailed => Reply(0x9310_u16),
+            Error::NetworkMismatch => Reply(0x9320_u16),
+            Error::BlindSigningDisabled => Reply(0x9330_u16),
+            Error::EmptyTransaction => Reply(0x9340_u16),
+            Error::ZeroKey => Reply(0x9350_u16),
+            Error::MetadataNotBound => Reply(0x9360_u16),
+            Error::TooManyOutputs => Reply(0x9370_u16),
+            Error::TransactionNotApproved => Reply(0x9380_u16),
+            Error::TransactionApproved => Reply(0x9390_u16),
+            Error::TooManyInputs => Reply(0x93a0_u16),
+            Error::UnknownInput => Reply(0x93b0_u16),
+            Error::MetadataAlreadyBound => Reply(0x93c0_u16),
+            Error::InvalidDerivationPath => Reply(0x93d0_u16),
         }
     }
 }
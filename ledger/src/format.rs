@@ -0,0 +1,155 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Amount formatting
+//! Amounts are held in micro Tari, but shown to the user as XTR with a fixed decimal point, e.g. `60000000` as
+//! `60.000000 XTR`. Formatting writes into a fixed buffer on the stack, so that review screens need no heap.
+
+/// The format of amounts shown on the review screens
+pub const XTR: AmountFormat = AmountFormat {
+    decimals: 6,
+    thousands_separator: Some(b','),
+    unit: " XTR",
+};
+
+// The most digits of a u64
+const MAX_DIGITS: usize = 20;
+// The longest unit suffix a format can have
+const MAX_UNIT_LENGTH: usize = 8;
+// The longest formatted amount: every digit, a separator for every three integer digits, the decimal point and the unit
+const MAX_AMOUNT_LENGTH: usize = MAX_DIGITS + (MAX_DIGITS - 1) / 3 + 1 + MAX_UNIT_LENGTH;
+
+/// How to format an amount
+pub struct AmountFormat {
+    /// The number of digits after the decimal point, at most 19
+    pub decimals: usize,
+    /// The byte that groups the integer digits by three, if any
+    pub thousands_separator: Option<u8>,
+    /// The unit appended to the number, of at most 8 bytes
+    pub unit: &'static str,
+}
+
+impl AmountFormat {
+    /// Format `value`, an amount in the smallest unit, with all decimals
+    pub fn format(&self, value: u64) -> FormattedAmount {
+        // The digits, least significant first, padded with zeros so that there is at least one integer digit
+        let mut digits = [b'0'; MAX_DIGITS];
+        let mut count = 0;
+        let mut rest = value;
+        loop {
+            digits[count] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            count += 1;
+            if rest == 0 {
+                break;
+            }
+        }
+        let count = count.max(self.decimals + 1);
+
+        let mut amount = FormattedAmount {
+            bytes: [0u8; MAX_AMOUNT_LENGTH],
+            length: 0,
+        };
+        for position in (0..count).rev() {
+            amount.push(digits[position]);
+            if position > self.decimals && (position - self.decimals) % 3 == 0 {
+                if let Some(separator) = self.thousands_separator {
+                    amount.push(separator);
+                }
+            }
+            if position == self.decimals && self.decimals > 0 {
+                amount.push(b'.');
+            }
+        }
+        for byte in self.unit.bytes() {
+            amount.push(byte);
+        }
+        amount
+    }
+}
+
+/// A formatted amount, held on the stack
+pub struct FormattedAmount {
+    bytes: [u8; MAX_AMOUNT_LENGTH],
+    length: usize,
+}
+
+impl FormattedAmount {
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or_default()
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.bytes[self.length] = byte;
+        self.length += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    #[test]
+    fn formats_xtr() {
+        for (value, expected) in [
+            (0, "0.000000 XTR"),
+            (1, "0.000001 XTR"),
+            (999_999, "0.999999 XTR"),
+            (1_000_000, "1.000000 XTR"),
+            (60_000_000, "60.000000 XTR"),
+            (999_999_999, "999.999999 XTR"),
+            (1_000_000_000, "1,000.000000 XTR"),
+            (1_234_567_890_123, "1,234,567.890123 XTR"),
+            (999_999_999_999_999, "999,999,999.999999 XTR"),
+            (1_000_000_000_000_000, "1,000,000,000.000000 XTR"),
+            (u64::MAX, "18,446,744,073,709.551615 XTR"),
+        ] {
+            assert_eq!(XTR.format(value).as_str(), expected);
+        }
+    }
+
+    #[test]
+    fn formats_without_decimals_or_separator() {
+        let integer = AmountFormat {
+            decimals: 0,
+            thousands_separator: Some(b' '),
+            unit: "",
+        };
+        assert_eq!(integer.format(0).as_str(), "0");
+        assert_eq!(integer.format(999).as_str(), "999");
+        assert_eq!(integer.format(1000).as_str(), "1 000");
+        assert_eq!(integer.format(u64::MAX).as_str(), "18 446 744 073 709 551 615");
+
+        let plain = AmountFormat {
+            decimals: 2,
+            thousands_separator: None,
+            unit: "",
+        };
+        assert_eq!(plain.format(5).as_str(), "0.05");
+        assert_eq!(plain.format(123_456).as_str(), "1234.56");
+    }
+
+    #[test]
+    fn formats_the_longest_amounts() {
+        // Every decimal of a u64, and every separator with the longest unit
+        let decimals = AmountFormat {
+            decimals: 19,
+            thousands_separator: Some(b','),
+            unit: "",
+        };
+        assert_eq!(decimals.format(u64::MAX).as_str(), "1.8446744073709551615");
+        assert_eq!(decimals.format(0).as_str(), "0.0000000000000000000");
+        let separators = AmountFormat {
+            decimals: 0,
+            thousands_separator: Some(b','),
+            unit: " 8 bytes",
+        };
+        assert_eq!(
+            separators.format(u64::MAX).as_str(),
+            "18,446,744,073,709,551,615 8 bytes"
+        );
+    }
+}
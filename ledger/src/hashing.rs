@@ -0,0 +1,139 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Domain separated hashing
+//! Every hash Tari computes is prefixed with a tag naming its domain, version and label, so that a hash made for one
+//! purpose can never be taken for another. The tag is written as `len || domain || ".v" || version || "." || label`,
+//! with `len` the little-endian u64 length of the rest of the tag, as in `tari_crypto`'s `DomainSeparation`, so hashes
+//! made here are byte-for-byte identical to those of `tari_crypto`.
+
+use core::marker::PhantomData;
+
+use digest::{Digest, Output};
+
+/// A hash domain, declared with `hash_domain!`
+pub trait DomainSeparation {
+    /// The version of the domain
+    fn version() -> u8;
+
+    /// The name of the domain
+    fn domain() -> &'static str;
+
+    /// Write the domain separation tag for `label` into `digest`
+    fn add_domain_separation_tag<D: Digest>(digest: &mut D, label: &str) {
+        let (version_offset, version) = byte_to_decimal_ascii_bytes(Self::version());
+        let version = &version[version_offset..];
+        let domain = Self::domain();
+        // The `.v` before the version, and the `.` before a label
+        let length = if label.is_empty() {
+            domain.len() + version.len() + 2
+        } else {
+            domain.len() + version.len() + label.len() + 3
+        };
+        digest.update((length as u64).to_le_bytes());
+        digest.update(domain);
+        digest.update(b".v");
+        digest.update(version);
+        if !label.is_empty() {
+            digest.update(b".");
+            digest.update(label);
+        }
+    }
+}
+
+/// A hasher whose input is prefixed with the domain separation tag of `M`
+pub struct DomainSeparatedHasher<D, M> {
+    inner: D,
+    _domain: PhantomData<M>,
+}
+
+impl<D: Digest, M: DomainSeparation> DomainSeparatedHasher<D, M> {
+    /// Create a hasher for the domain `M` and the label `label`
+    pub fn new_with_label(label: &'static str) -> Self {
+        let mut inner = D::new();
+        M::add_domain_separation_tag(&mut inner, label);
+        Self {
+            inner,
+            _domain: PhantomData,
+        }
+    }
+
+    /// Add `data` to the hash
+    pub fn chain(mut self, data: impl AsRef<[u8]>) -> Self {
+        self.inner.update(data);
+        self
+    }
+
+    pub fn finalize(self) -> DomainSeparatedHash<D> {
+        DomainSeparatedHash {
+            output: self.inner.finalize(),
+        }
+    }
+}
+
+/// The output of a `DomainSeparatedHasher`
+pub struct DomainSeparatedHash<D: Digest> {
+    output: Output<D>,
+}
+
+impl<D: Digest> AsRef<[u8]> for DomainSeparatedHash<D> {
+    fn as_ref(&self) -> &[u8] {
+        self.output.as_slice()
+    }
+}
+
+// The decimal ASCII digits of `byte`, right aligned, with the offset of the first digit
+fn byte_to_decimal_ascii_bytes(mut byte: u8) -> (usize, [u8; 3]) {
+    let mut digits = [b'0'; 3];
+    let mut offset = 2;
+    loop {
+        digits[offset] = b'0' + byte % 10;
+        byte /= 10;
+        if byte == 0 {
+            break;
+        }
+        offset -= 1;
+    }
+    (offset, digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use tari_crypto::hashing::{DomainSeparatedHasher as TariHasher, DomainSeparation as TariDomainSeparation};
+    use testmacro::test_item as test;
+
+    use super::*;
+    use crate::blake2::Blake256;
+
+    hash_domain!(TestDomain, "com.tari.ledger.test", 0);
+    tari_crypto::hash_domain!(TariTestDomain, "com.tari.ledger.test", 0);
+    hash_domain!(LongVersionDomain, "com.tari.ledger.test", 123);
+    tari_crypto::hash_domain!(TariLongVersionDomain, "com.tari.ledger.test", 123);
+
+    #[test]
+    fn hashes_match_tari_crypto() {
+        for label in ["", "label"] {
+            let hash = DomainSeparatedHasher::<Blake256, TestDomain>::new_with_label(label)
+                .chain(b"data")
+                .finalize();
+            let expected = TariHasher::<Blake256, TariTestDomain>::new_with_label(label)
+                .chain(b"data")
+                .finalize();
+            assert_eq!(hash.as_ref(), expected.as_ref());
+
+            let hash = DomainSeparatedHasher::<Blake256, LongVersionDomain>::new_with_label(label).finalize();
+            let expected = TariHasher::<Blake256, TariLongVersionDomain>::new_with_label(label).finalize();
+            assert_eq!(hash.as_ref(), expected.as_ref());
+        }
+    }
+
+    #[test]
+    fn tags_match_tari_crypto() {
+        let mut digest = Blake256::new();
+        TestDomain::add_domain_separation_tag(&mut digest, "label");
+        let mut expected = Blake256::new();
+        TariTestDomain::add_domain_separation_tag(&mut expected, "label");
+        assert_eq!(digest.finalize().as_slice(), expected.finalize().as_slice());
+    }
+}
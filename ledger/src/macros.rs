@@ -97,3 +97,22 @@ macro_rules! define_mul_variants {
         }
     };
 }
+
+/// Declare a hash domain `$name` for `DomainSeparatedHasher`, with the name `$domain` and the version `$version`, as
+/// `tari_crypto`'s macro of the same name does
+macro_rules! hash_domain {
+    ($name:ident, $domain:expr, $version:expr) => {
+        #[derive(Clone, Debug)]
+        pub struct $name;
+
+        impl $crate::hashing::DomainSeparation for $name {
+            fn version() -> u8 {
+                $version
+            }
+
+            fn domain() -> &'static str {
+                $domain
+            }
+        }
+    };
+}
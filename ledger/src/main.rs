@@ -23,11 +23,40 @@
 #![no_std]
 #![no_main]
 #![feature(alloc_error_handler)]
+#![cfg_attr(test, feature(custom_test_frameworks))]
+#![cfg_attr(test, reexport_test_harness_main = "test_main")]
+#![cfg_attr(test, test_runner(nanos_sdk::testing::sdk_test_runner))]
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 
 extern crate alloc;
+#[macro_use]
+mod macros;
+mod address;
+mod approval;
+mod blake2;
+mod covenant;
+#[cfg(feature = "cx-ecc")]
+mod cx_ristretto;
+mod display;
+mod encrypted_data;
+mod errors;
+mod format;
+mod hashing;
+mod menu;
+mod output_features;
+mod pedersen;
+mod ristretto_keys;
+mod schnorr;
+mod script;
+mod settings;
+mod shared_secret;
+mod stealth;
+mod timeout;
+mod transaction;
+
 use core::{marker::PhantomData, mem::MaybeUninit};
 
-use blake2::Blake2b;
+use ::blake2::Blake2b;
 use borsh::{
     maybestd::io::{Result as BorshResult, Write},
     BorshSerialize,
@@ -38,31 +67,49 @@ use nanos_sdk::{
     ecc::{bip32_derive, CurvesId, Secret},
     io,
     io::SyscallError,
-    random,
 };
 use nanos_ui::ui;
-use tari_crypto::{
-    commitment::HomomorphicCommitmentFactory,
-    hash_domain,
+
+use crate::{
+    address::{TariAddress, TARI_ADDRESS_LENGTH},
+    covenant::{Covenant, Filter},
+    display::Message,
+    encrypted_data::{EncryptedData, ENCRYPTED_DATA_LENGTH},
+    errors::Error,
     hashing::DomainSeparation,
-    keys::PublicKey,
-    ristretto::{
-        pedersen::extended_commitment_factory::ExtendedPedersenCommitmentFactory,
-        RistrettoPublicKey,
-        RistrettoSchnorr,
-        RistrettoSecretKey,
+    menu::{HomeEntry, HomeMenu, Selection, SettingsEntry},
+    output_features::{OutputFeatures, SideChainFeature},
+    pedersen::{ExtensionDegree, PedersenCommitment, PedersenCommitmentFactory, MAX_EXTENSION_DEGREE},
+    ristretto_keys::{RistrettoPublicKey, RistrettoSecretKey},
+    schnorr::SchnorrSignature,
+    script::{ScriptKind, ScriptSummary},
+    settings::Settings,
+    shared_secret::{shared_secret, SharedSecretLabel},
+    stealth::{
+        seal_nonce,
+        stealth_script,
+        stealth_script_key,
+        stealth_script_public_key,
+        stealth_shared_secret,
+        unseal_nonce,
+        OutputScanner,
+        MAX_SCAN_ADDRESSES,
+        SEALED_NONCE_LENGTH,
+        STEALTH_RECORD_LENGTH,
     },
-    tari_utilities::ByteArray,
+    timeout::{next_timeout_minutes, InactivityTimer},
+    transaction::{next_high_fee_threshold, TransactionState},
 };
 
-use crate::alloc::string::{String, ToString};
-
 nanos_sdk::set_panic!(nanos_sdk::exiting_panic);
 
 /// App Version parameters
 const NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// The number of accounts the settings menu cycles through for the default account
+const SETTINGS_ACCOUNTS: u64 = 10;
+
 enum Instruction {
     GetVersion,
     Sign,
@@ -70,6 +117,17 @@ enum Instruction {
     GetPublicKey,
     GetPublicNonce,
     Exit,
+    SignAdaptor,
+    RecoverAdaptorSecret,
+    EncryptData,
+    DecryptData,
+    SignTransaction,
+    OneSidedPayment,
+    ScanOutputs,
+    SharedSecret,
+    // Timing scalar multiplications is only of use while developing, see the README
+    #[cfg(feature = "benchmark")]
+    Benchmark,
     BadInstruction(u8),
 }
 
@@ -82,6 +140,16 @@ impl From<io::ApduHeader> for Instruction {
             0x04 => Self::GetPublicKey,
             0x05 => Self::GetPublicNonce,
             0x06 => Self::Exit,
+            0x07 => Self::SignAdaptor,
+            0x08 => Self::RecoverAdaptorSecret,
+            0x09 => Self::EncryptData,
+            0x0a => Self::DecryptData,
+            0x0b => Self::SignTransaction,
+            0x0c => Self::OneSidedPayment,
+            0x0d => Self::ScanOutputs,
+            0x0e => Self::SharedSecret,
+            #[cfg(feature = "benchmark")]
+            0x0f => Self::Benchmark,
             other => Self::BadInstruction(other),
         }
     }
@@ -89,66 +157,83 @@ impl From<io::ApduHeader> for Instruction {
 
 hash_domain!(TransactionHashDomain, "com.tari.base_layer.core.transactions", 0);
 
+/// State kept between APDUs, for instructions whose input is streamed over several of them
+/// - The event loop replaces it with the default after the inactivity timeout, which wipes the secret keys it holds.
+#[derive(Default)]
+struct Session {
+    /// The transaction being signed, see `handle_transaction_step`
+    transaction: Option<TransactionState>,
+    /// The output scan in progress, see `handle_scan_step`
+    scanner: Option<OutputScanner>,
+}
+
 use nanos_sdk::io::{ApduHeader, Reply, StatusWords};
 
+#[cfg(not(test))]
 #[no_mangle]
 extern "C" fn sample_main() {
     let mut comm = io::Comm::new();
+    let mut session = Session::default();
+    let mut menu = HomeMenu::default();
+    let mut timer = InactivityTimer::new();
     init();
-    ui::SingleMessage::new("Tari test app").show();
+    menu.show();
     loop {
         let event = comm.next_event::<ApduHeader>();
+        if !matches!(event, io::Event::Ticker) {
+            timer.reset();
+        }
         match event {
-            io::Event::Button(ButtonEvent::BothButtonsRelease) => nanos_sdk::exit_app(0),
+            // The first button released after an error only dismisses it
+            io::Event::Button(
+                ButtonEvent::LeftButtonRelease | ButtonEvent::RightButtonRelease | ButtonEvent::BothButtonsRelease,
+            ) if menu.dismiss_error() => {},
+            io::Event::Button(ButtonEvent::LeftButtonRelease) => {
+                menu.previous();
+                menu.show();
+            },
             io::Event::Button(ButtonEvent::RightButtonRelease) => {
-                display_infos();
+                menu.next();
+                menu.show();
+            },
+            io::Event::Button(ButtonEvent::BothButtonsRelease) => {
+                match menu.select() {
+                    Some(Selection::Home(entry)) => select_home_entry(&mut menu, entry),
+                    Some(Selection::Setting(entry)) => change_setting(entry),
+                    None => {},
+                }
+                menu.show();
             },
-            io::Event::Button(ButtonEvent::LeftButtonPress) => {},
             io::Event::Button(_) => {},
-            io::Event::Command(apdu_header) => match handle_apdu(&mut comm, apdu_header.into()) {
+            io::Event::Command(apdu_header) => match handle_apdu(&mut comm, apdu_header.into(), &mut session) {
                 Ok(()) => comm.reply_ok(),
-                Err(e) => comm.reply(e),
+                Err(e) => {
+                    // The host gets the status word at once, the user only sees the error once the device is idle
+                    if !is_user_rejection(e.0) {
+                        menu.report_error(e.0);
+                    }
+                    comm.reply(e);
+                },
+            },
+            io::Event::Ticker => {
+                if timer.tick() {
+                    session = Session::default();
+                    menu = HomeMenu::default();
+                    menu.show();
+                }
+                menu.tick();
             },
-            io::Event::Ticker => {},
-        }
-    }
-}
-
-// Convert a u64 to a string without using the standard library
-fn u64_to_string(number: u64) -> String {
-    let mut buffer = [0u8; 20]; // Maximum length for a 64-bit integer (including null terminator)
-    let mut pos = 0;
-
-    if number == 0 {
-        buffer[pos] = b'0';
-        pos += 1;
-    } else {
-        let mut num = number;
-
-        let mut digits = [0u8; 20];
-        let mut num_digits = 0;
-
-        while num > 0 {
-            digits[num_digits] = b'0' + (num % 10) as u8;
-            num /= 10;
-            num_digits += 1;
-        }
-
-        while num_digits > 0 {
-            num_digits -= 1;
-            buffer[pos] = digits[num_digits];
-            pos += 1;
         }
     }
-
-    String::from_utf8_lossy(&buffer[..pos]).to_string()
 }
 
-// Convert a single byte to a hex string
-fn byte_to_hex(byte: u8) -> String {
-    const HEX_CHARS: [u8; 16] = *b"0123456789abcdef";
-    let hex = [HEX_CHARS[(byte >> 4) as usize], HEX_CHARS[(byte & 0x0F) as usize]];
-    String::from_utf8_lossy(&hex).to_string()
+// The tests run in place of the app, reporting their results through semihosting, see the README
+#[cfg(test)]
+#[no_mangle]
+extern "C" fn sample_main() {
+    init();
+    test_main();
+    nanos_sdk::exit_app(0);
 }
 
 // Get a raw 32 byte key from the BIP32 path.
@@ -162,39 +247,216 @@ fn byte_to_hex(byte: u8) -> String {
 //     d8a57c1be0c52e9643485e77aac56d72fa6c4eb831466c2abd2d320c82d3d14929811c598c13d431bad433e037dbd97265492cea42bc2e3aad15440210a20a2d0000000000000000000000000000000000000000000000000000000000000000
 //  - This function applies domain separated hashing to the 64 byte private key of the returned buffer to get 32
 //    uniformly distributed random bytes.
-fn get_raw_key_hash(path: &[u32]) -> Result<[u8; 32], String> {
+//  - A failure is not shown here, as the event loop reports every error once the device is idle.
+fn get_raw_key(path: &[u32]) -> Result<[u8; 32], SyscallError> {
     let mut key = Secret::<96>::new();
-    let raw_key_64 = match bip32_derive(CurvesId::Ed25519, path, key.as_mut()) {
-        Ok(_) => {
-            let binding = &key.as_ref()[..64];
-            let raw_key_64: [u8; 64] = match binding.try_into() {
-                Ok(v) => v,
-                Err(_) => return Err("Err: 64 byte slice".to_string()),
-            };
-            raw_key_64
-        },
-        Err(_) => return Err("Err: InvalidParameter".to_string()),
-    };
+    bip32_derive(CurvesId::Ed25519, path, key.as_mut()).map_err(|_| SyscallError::InvalidParameter)?;
+    let raw_key_64: [u8; 64] = key.as_ref()[..64]
+        .try_into()
+        .map_err(|_| SyscallError::InvalidParameter)?;
 
     Ok(DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("raw_key")
         .chain(&raw_key_64)
         .finalize())
 }
 
-fn get_raw_key(path: &[u32]) -> Result<[u8; 32], SyscallError> {
-    match get_raw_key_hash(&path) {
-        Ok(val) => Ok(val),
-        Err(e) => {
-            let mut msg = "".to_string();
-            msg.push_str("Err: raw key >>...");
-            ui::SingleMessage::new(&msg).show_and_wait();
-            ui::SingleMessage::new(&e).show();
-            Err(SyscallError::InvalidParameter.into())
+// Whether `status_word` only tells the host that the user turned the request down, which needs no error screen
+fn is_user_rejection(status_word: u16) -> bool {
+    status_word == StatusWords::UserCancelled as u16 || status_word == Reply::from(Error::TransactionRejected).0
+}
+
+/// The branch, i.e. the BIP32 change level, of the keys derived under `m/44'/535348'/<account>'`
+#[derive(Clone, Copy)]
+enum KeyBranch {
+    /// Spend keys, as returned by `GetPublicKey`
+    Spend = 0,
+    /// Commitment masks, one per output index
+    CommitmentMask = 1,
+    /// View keys: the one at index 0 encrypts and decrypts output data and is the account's address, and output scans
+    /// look for one-sided payments to the first few
+    DataEncryption = 2,
+    /// Script keys of outputs the account receives
+    ScriptKey = 3,
+    // Branches 4 and 5 held the sender offset keys of outputs the account creates and the nonces `r` of one-sided
+    // payments, which are now random keys generated for each output, so that the host cannot have one used twice
+}
+
+impl KeyBranch {
+    // The branch selected by P1 for `GetPublicKey` and `SharedSecret`: spend, view and script keys are the keys of the
+    // account that other parties know the public keys of, unlike commitment masks
+    fn from_p1(p1: u8) -> Result<Self, Reply> {
+        match p1 {
+            0 => Ok(Self::Spend),
+            2 => Ok(Self::DataEncryption),
+            3 => Ok(Self::ScriptKey),
+            _ => Err(StatusWords::BadP1P2.into()),
+        }
+    }
+}
+
+// Build the BIP32 path `m/44'/535348'/<account>'/<branch>/<index>`.
+// - The account and index must be below 2^31, so that the key derived is the one of the numbers the user is shown,
+//   rather than that of their lower 32 bits or of a hardened index.
+fn bip32_path(account: u64, branch: KeyBranch, index: u64) -> Result<[u32; 5], Reply> {
+    const HARDENED: u32 = 0x8000_0000;
+    if account >= u64::from(HARDENED) || index >= u64::from(HARDENED) {
+        return Err(Error::InvalidDerivationPath.into());
+    }
+    Ok([
+        44 | HARDENED,
+        535348 | HARDENED,
+        account as u32 | HARDENED,
+        branch as u32,
+        index as u32,
+    ])
+}
+
+// Derive the secret key at `m/44'/535348'/<account>'/<branch>/<index>`
+fn derive_secret_key(account: u64, branch: KeyBranch, index: u64) -> Result<RistrettoSecretKey, Reply> {
+    let raw_key = get_raw_key(&bip32_path(account, branch, index)?)?;
+    Ok(RistrettoSecretKey::from_bytes(&raw_key)?)
+}
+
+// Derive the blinding factors of the extended commitment for `output_index`. The first is the commitment mask at
+// `m/44'/535348'/<account>'/1/<output_index>`, so that a default commitment is unchanged by the extension.
+fn derive_commitment_masks(
+    account: u64,
+    output_index: u64,
+    extension_degree: ExtensionDegree,
+) -> Result<[RistrettoSecretKey; MAX_EXTENSION_DEGREE], Reply> {
+    let mask = derive_secret_key(account, KeyBranch::CommitmentMask, output_index)?;
+    extend_commitment_mask(mask, extension_degree)
+}
+
+// Extend a commitment mask to the blinding factors of an extended commitment, hashing each extra one from it, so that
+// the first mask is all that encrypted data needs to hold
+fn extend_commitment_mask(
+    mask: RistrettoSecretKey,
+    extension_degree: ExtensionDegree,
+) -> Result<[RistrettoSecretKey; MAX_EXTENSION_DEGREE], Reply> {
+    let mut masks: [RistrettoSecretKey; MAX_EXTENSION_DEGREE] = Default::default();
+    for (i, extra_mask) in masks.iter_mut().enumerate().take(extension_degree.size()).skip(1) {
+        let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("extended_commitment_mask")
+            .chain(&mask.as_bytes())
+            .chain(&(i as u64))
+            .finalize();
+        *extra_mask = RistrettoSecretKey::from_bytes(&hash)?;
+    }
+    masks[0] = mask;
+    Ok(masks)
+}
+
+// Read P1, the third byte of the APDU header
+fn get_p1(comm: &io::Comm) -> u8 {
+    comm.get(2, 3)[0]
+}
+
+// Read P2, the fourth byte of the APDU header
+fn get_p2(comm: &io::Comm) -> u8 {
+    comm.get(3, 4)[0]
+}
+
+// Read an extension degree, where 0 is also accepted for the default degree of 1
+fn get_extension_degree(byte: u8) -> Result<ExtensionDegree, Reply> {
+    Ok(ExtensionDegree::try_from_size(byte.max(1) as usize)?)
+}
+
+// Check that the APDU, header included, is at least `length` bytes long, so that fixed fields are never read from what
+// is left in the buffer by an earlier APDU
+fn check_length(comm: &io::Comm, length: usize) -> Result<(), Reply> {
+    if comm.rx < length {
+        return Err(StatusWords::BadLen.into());
+    }
+    Ok(())
+}
+
+// Read the length of the APDU data, the fifth byte of the header, after checking that the APDU holds that much data, so
+// that data of variable length is never read from what is left in the buffer by an earlier APDU
+fn get_data_length(comm: &io::Comm) -> Result<usize, Reply> {
+    let data_length = comm.get(4, 5)[0] as usize;
+    check_length(comm, 5 + data_length)?;
+    Ok(data_length)
+}
+
+// Read a little-endian u64 from the APDU buffer at `offset`
+fn get_u64(comm: &io::Comm, offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.clone_from_slice(comm.get(offset, offset + 8));
+    u64::from_le_bytes(bytes)
+}
+
+// Get the key that encrypts the value and mask of an account's outputs, with the public key of the other party who can
+// derive it too, if any. `selector` selects the key:
+// - 0: the account's view key itself, for outputs the account sends to itself
+// - 1: the view key's Diffie-Hellman shared secret with the 32 byte public key at `offset`, for outputs exchanged with
+//   another party who holds the matching secret key
+// - 2: the shared secret `r.K` of a one-sided payment, for the sealed nonce `r` that `OneSidedPayment` replied with ||
+//   recipient public key `K` (32) at `offset`, which the recipient recomputes as `k.R`
+fn data_encryption_key(
+    comm: &io::Comm,
+    account: u64,
+    selector: u8,
+    offset: usize,
+) -> Result<(RistrettoSecretKey, Option<RistrettoPublicKey>), Reply> {
+    let (secret_key, public_key) = match selector {
+        0 => return Ok((derive_secret_key(account, KeyBranch::DataEncryption, 0)?, None)),
+        1 => {
+            check_length(comm, offset + 32)?;
+            let view_key = derive_secret_key(account, KeyBranch::DataEncryption, 0)?;
+            (view_key, RistrettoPublicKey::from_bytes(comm.get(offset, offset + 32))?)
+        },
+        2 => {
+            check_length(comm, offset + SEALED_NONCE_LENGTH + 32)?;
+            let view_key = derive_secret_key(account, KeyBranch::DataEncryption, 0)?;
+            let sealed_nonce = EncryptedData::from_bytes(comm.get(offset, offset + SEALED_NONCE_LENGTH))?;
+            let public_key = RistrettoPublicKey::from_bytes(
+                comm.get(offset + SEALED_NONCE_LENGTH, offset + SEALED_NONCE_LENGTH + 32),
+            )?;
+            (unseal_nonce(&view_key, &sealed_nonce, &public_key)?, public_key)
         },
+        _ => return Err(StatusWords::BadP1P2.into()),
+    };
+    let encryption_key = shared_secret(&secret_key, &public_key, SharedSecretLabel::DataEncryptionKey)?;
+    Ok((encryption_key, Some(public_key)))
+}
+
+// Decode an address, which must be for the network chosen in the settings
+fn parse_address(bytes: &[u8]) -> Result<TariAddress, Reply> {
+    let address = TariAddress::from_bytes(bytes)?;
+    if address.network() != Settings::load().network {
+        return Err(Error::NetworkMismatch.into());
+    }
+    Ok(address)
+}
+
+// Compute the challenge for a signature by `public_key` with `public_nonce` on the 32 byte challenge sent by the host.
+fn script_challenge(
+    public_key: &RistrettoPublicKey,
+    public_nonce: &RistrettoPublicKey,
+    challenge: &[u8; 32],
+) -> [u8; 32] {
+    DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("script_challenge")
+        .chain(&public_key.as_bytes())
+        .chain(&public_nonce.as_bytes())
+        .chain(challenge)
+        .finalize()
+}
+
+// Verify a signature before it is released to the host. A fault injected while signing could produce a signature from
+// which the private key can be computed, so a signature that does not verify must never leave the device.
+fn verify_before_release(
+    signature: &SchnorrSignature,
+    public_key: &RistrettoPublicKey,
+    challenge: &[u8],
+) -> Result<(), Reply> {
+    if signature.verify_challenge(public_key, challenge) {
+        Ok(())
+    } else {
+        Err(Error::SignatureVerificationFailed.into())
     }
 }
 
-fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Reply> {
+fn handle_apdu(comm: &mut io::Comm, instruction: Instruction, session: &mut Session) -> Result<(), Reply> {
     if comm.rx == 0 {
         return Err(io::StatusWords::NothingReceived.into());
     }
@@ -214,27 +476,23 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
             comm.reply_ok();
         },
         Instruction::Sign => {
-            ui::SingleMessage::new("Sign...").show();
             // first 5 bytes are instruction details
             let offset = 5;
+            check_length(comm, offset + 32)?;
             let challenge = ArrayString::<32>::from_bytes(comm.get(offset, offset + 32));
-            let path: [u32; 5] = nanos_sdk::ecc::make_bip32_path(b"m/44'/535348'/0'/0/0");
+            approve_blind_signing(comm, challenge.bytes())?;
+            ui::SingleMessage::new("Sign...").show();
 
-            let raw_key = get_raw_key(&path)?;
-            let private_key = RistrettoSecretKey::from_bytes(&raw_key).unwrap();
+            let private_key = derive_secret_key(Settings::load().default_account, KeyBranch::Spend, 0)?;
             let public_key = RistrettoPublicKey::from_secret_key(&private_key);
 
-            let mut nonce_bytes = [0u8; 32];
-            random::rand_bytes(&mut nonce_bytes);
-            let private_nonce = RistrettoSecretKey::from_bytes(&nonce_bytes).unwrap();
+            let private_nonce = RistrettoSecretKey::random();
             let public_nonce = RistrettoPublicKey::from_secret_key(&private_nonce);
 
-            let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("script_challenge")
-                .chain(&public_key.as_bytes())
-                .chain(&public_nonce.as_bytes())
-                .chain(challenge.bytes())
-                .finalize();
-            let signature = RistrettoSchnorr::sign_raw(&private_key, private_nonce, &hash).unwrap();
+            let hash = script_challenge(&public_key, &public_nonce, challenge.bytes());
+            let signature = SchnorrSignature::sign_raw(&private_key, private_nonce, &hash).unwrap();
+            let hash = script_challenge(&public_key, signature.get_public_nonce(), challenge.bytes());
+            verify_before_release(&signature, &public_key, &hash)?;
             let sig = signature.get_signature().as_bytes();
             let nonce = signature.get_public_nonce().as_bytes();
 
@@ -249,15 +507,17 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
             ui::SingleMessage::new("Commitment...").show();
             // first 5 bytes are instruction details
             let offset = 5;
-            let mut value_bytes = [0u8; 8];
-            value_bytes.clone_from_slice(comm.get(offset, offset + 8));
-            let value = u64::from_le_bytes(value_bytes);
-            let path: [u32; 5] = nanos_sdk::ecc::make_bip32_path(b"m/44'/535348'/0'/0/0");
+            check_length(comm, offset + 24)?;
+            let value = get_u64(comm, offset);
+            let account = get_u64(comm, offset + 8);
+            let output_index = get_u64(comm, offset + 16);
 
-            let raw_key = get_raw_key(&path)?;
-            let k = RistrettoSecretKey::from_bytes(&raw_key).unwrap();
-            let com_factories = ExtendedPedersenCommitmentFactory::default();
-            let commitment = com_factories.commit_value(&k, value);
+            // P1 is the extension degree
+            let extension_degree = get_extension_degree(get_p1(comm))?;
+
+            let masks = derive_commitment_masks(account, output_index, extension_degree)?;
+            let commitment = PedersenCommitmentFactory::new_with_extension_degree(extension_degree)
+                .commit_value_extended(&masks[..extension_degree.size()], value)?;
 
             comm.append(&[1]); // version
             comm.append(commitment.as_bytes());
@@ -268,21 +528,12 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
             ui::SingleMessage::new("GetPublicKey...").show();
             // first 5 bytes are instruction details
             let offset = 5;
-            let mut account_bytes = [0u8; 8];
-            account_bytes.clone_from_slice(comm.get(offset, offset + 8));
-            let account = u64_to_string(u64::from_le_bytes(account_bytes));
-            let mut address_index_bytes = [0u8; 8];
-            address_index_bytes.clone_from_slice(comm.get(offset + 8, offset + 8 + 8));
-            let address_index = u64_to_string(u64::from_le_bytes(address_index_bytes));
-
-            let mut bip32_path = "m/44'/535348'/".to_string();
-            bip32_path.push_str(&account);
-            bip32_path.push_str(&"'/0/");
-            bip32_path.push_str(&address_index);
-            let path: [u32; 5] = nanos_sdk::ecc::make_bip32_path(bip32_path.as_bytes());
-
-            let raw_key = get_raw_key(&path)?;
-            let k = RistrettoSecretKey::from_bytes(&raw_key).unwrap();
+            check_length(comm, offset + 16)?;
+            let account = get_u64(comm, offset);
+            let address_index = get_u64(comm, offset + 8);
+            let branch = KeyBranch::from_p1(get_p1(comm))?;
+
+            let k = derive_secret_key(account, branch, address_index)?;
             let pk = RistrettoPublicKey::from_secret_key(&k);
 
             comm.append(&[1]); // version
@@ -293,9 +544,7 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
         Instruction::GetPublicNonce => {
             ui::SingleMessage::new("GetPublicNonce...").show();
 
-            let mut nonce_bytes = [0u8; 32];
-            random::rand_bytes(&mut nonce_bytes);
-            let private_nonce = RistrettoSecretKey::from_bytes(&nonce_bytes).unwrap();
+            let private_nonce = RistrettoSecretKey::random();
             let public_nonce = RistrettoPublicKey::from_secret_key(&private_nonce);
 
             comm.append(&[1]); // version
@@ -303,11 +552,211 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
             ui::SingleMessage::new("GetPublicNonce... Done").show();
             comm.reply_ok();
         },
+        Instruction::SignAdaptor => {
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 64)?;
+            let challenge = ArrayString::<32>::from_bytes(comm.get(offset, offset + 32));
+            let adaptor_point = RistrettoPublicKey::from_bytes(comm.get(offset + 32, offset + 64))?;
+            approve_blind_signing(comm, challenge.bytes())?;
+            ui::SingleMessage::new("SignAdaptor...").show();
+
+            let private_key = derive_secret_key(Settings::load().default_account, KeyBranch::Spend, 0)?;
+            let public_key = RistrettoPublicKey::from_secret_key(&private_key);
+
+            let private_nonce = RistrettoSecretKey::random();
+            let public_nonce = RistrettoPublicKey::from_secret_key(&private_nonce);
+
+            // The challenge commits to the adapted nonce `R + T`, so that the completed signature `(R + T, s' + t)` is
+            // an ordinary signature as returned by `Sign`
+            let hash = script_challenge(&public_key, &(&public_nonce + &adaptor_point), challenge.bytes());
+            let pre_signature = SchnorrSignature::sign_raw(&private_key, private_nonce, &hash).unwrap();
+            let adapted_nonce = pre_signature.get_public_nonce() + &adaptor_point;
+            let hash = script_challenge(&public_key, &adapted_nonce, challenge.bytes());
+            verify_before_release(&pre_signature, &public_key, &hash)?;
+            let sig = pre_signature.get_signature().as_bytes();
+            let nonce = pre_signature.get_public_nonce().as_bytes();
+
+            comm.append(&[1]); // version
+            comm.append(public_key.as_bytes());
+            comm.append(sig);
+            comm.append(nonce);
+            ui::SingleMessage::new("SignAdaptor... Done").show();
+            comm.reply_ok();
+        },
+        Instruction::RecoverAdaptorSecret => {
+            ui::SingleMessage::new("RecoverAdaptorSecret...").show();
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 96)?;
+            let adaptor_point = RistrettoPublicKey::from_bytes(comm.get(offset, offset + 32))?;
+            let pre_signature = RistrettoSecretKey::from_bytes(comm.get(offset + 32, offset + 64))?;
+            let signature = RistrettoSecretKey::from_bytes(comm.get(offset + 64, offset + 96))?;
+
+            // t = s - s', which must be the discrete log of the adaptor point the pre-signature was made for
+            let adaptor_secret = &signature - &pre_signature;
+            if RistrettoPublicKey::from_secret_key(&adaptor_secret) != adaptor_point {
+                return Err(Error::AdaptorMismatch.into());
+            }
+
+            comm.append(&[1]); // version
+            comm.append(adaptor_secret.as_bytes());
+            ui::SingleMessage::new("RecoverAdaptorSecret... Done").show();
+            comm.reply_ok();
+        },
+        Instruction::EncryptData => {
+            ui::SingleMessage::new("EncryptData...").show();
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 24)?;
+            let value = get_u64(comm, offset);
+            let account = get_u64(comm, offset + 8);
+            let output_index = get_u64(comm, offset + 16);
+            // P1 selects the encryption key, see `data_encryption_key`, and P2 is the extension degree
+            let (encryption_key, other_party) = data_encryption_key(comm, account, get_p1(comm), offset + 24)?;
+            let extension_degree = get_extension_degree(get_p2(comm))?;
+            // The masks are derived first, so that the user is never shown an output index the device refuses
+            let masks = derive_commitment_masks(account, output_index, extension_degree)?;
+            // The other party can decrypt the mask, with which it could sign for the output, so the user must agree
+            if let Some(public_key) = other_party {
+                if !approve_mask_sharing(comm, account, output_index, &public_key) {
+                    return Err(StatusWords::UserCancelled.into());
+                }
+            }
+
+            let commitment = PedersenCommitmentFactory::new_with_extension_degree(extension_degree)
+                .commit_value_extended(&masks[..extension_degree.size()], value)?;
+            let encrypted_data = EncryptedData::encrypt_data(&encryption_key, &commitment, value, &masks[0])?;
+
+            comm.append(&[1]); // version
+            comm.append(commitment.as_bytes());
+            comm.append(encrypted_data.as_bytes());
+            ui::SingleMessage::new("EncryptData... Done").show();
+            comm.reply_ok();
+        },
+        Instruction::DecryptData => {
+            ui::SingleMessage::new("DecryptData...").show();
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 40 + ENCRYPTED_DATA_LENGTH)?;
+            let account = get_u64(comm, offset);
+            let commitment = PedersenCommitment::from_bytes(comm.get(offset + 8, offset + 40))?;
+            let encrypted_data = EncryptedData::from_bytes(comm.get(offset + 40, offset + 40 + ENCRYPTED_DATA_LENGTH))?;
+            // P1 selects the encryption key, see `data_encryption_key`, and P2 is the extension degree
+            let (encryption_key, _) =
+                data_encryption_key(comm, account, get_p1(comm), offset + 40 + ENCRYPTED_DATA_LENGTH)?;
+            let extension_degree = get_extension_degree(get_p2(comm))?;
+
+            let (value, mask) = encrypted_data.decrypt_data(&encryption_key, &commitment)?;
+            // Only an output that the decrypted value and mask open can be recovered. The mask stays on the device, as
+            // it is the key of the output's share of the kernel excess, and `SignTransaction` recovers it again to
+            // spend the output, see step 14.
+            let masks = extend_commitment_mask(mask.clone(), extension_degree)?;
+            let expected = PedersenCommitmentFactory::new_with_extension_degree(extension_degree)
+                .commit_value_extended(&masks[..extension_degree.size()], value)?;
+            if expected != commitment {
+                return Err(Error::DecryptFailed.into());
+            }
+
+            comm.append(&[1]); // version
+            comm.append(&value.to_le_bytes());
+            ui::SingleMessage::new("DecryptData... Done").show();
+            comm.reply_ok();
+        },
+        Instruction::SignTransaction => {
+            let result = handle_transaction_step(comm, &mut session.transaction);
+            // Any failure aborts the transaction, so that the host has to stream it again from the start
+            if result.is_err() {
+                session.transaction = None;
+            }
+            result?;
+        },
+        Instruction::ScanOutputs => {
+            let result = handle_scan_step(comm, &mut session.scanner);
+            if result.is_err() {
+                session.scanner = None;
+            }
+            result?;
+        },
+        Instruction::OneSidedPayment => {
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 8 + TARI_ADDRESS_LENGTH)?;
+            let account = get_u64(comm, offset);
+            let address = parse_address(comm.get(offset + 8, offset + 8 + TARI_ADDRESS_LENGTH))?;
+            if !approve_address(comm, &address) {
+                return Err(StatusWords::UserCancelled.into());
+            }
+            ui::SingleMessage::new("OneSidedPayment...").show();
+
+            // The nonce and the shared secret never leave the device, only `R` and `K_s` are returned, with the nonce
+            // sealed for `EncryptData` to derive the key of the output's encrypted data
+            let nonce = RistrettoSecretKey::random();
+            let public_nonce = RistrettoPublicKey::from_secret_key(&nonce);
+            let shared_secret = stealth_shared_secret(&nonce, address.public_key())?;
+            let script_public_key = stealth_script_public_key(&shared_secret, address.public_key());
+            let view_key = derive_secret_key(account, KeyBranch::DataEncryption, 0)?;
+            let sealed_nonce = seal_nonce(&view_key, &nonce, address.public_key())?;
+
+            comm.append(&[1]); // version
+            comm.append(public_nonce.as_bytes());
+            comm.append(script_public_key.as_bytes());
+            comm.append(sealed_nonce.as_bytes());
+            ui::SingleMessage::new("OneSidedPayment... Done").show();
+            comm.reply_ok();
+        },
+        Instruction::SharedSecret => {
+            // first 5 bytes are instruction details
+            let offset = 5;
+            check_length(comm, offset + 49)?;
+            let account = get_u64(comm, offset);
+            let index = get_u64(comm, offset + 8);
+            let public_key = RistrettoPublicKey::from_bytes(comm.get(offset + 16, offset + 48))?;
+            let label = SharedSecretLabel::from_byte(comm.get(offset + 48, offset + 49)[0])?;
+            let branch = KeyBranch::from_p1(get_p1(comm))?;
+
+            // Spend keys control funds, and the secrets of view keys are the masks, encryption keys and script keys of
+            // the one-sided payments the account receives, as well as the keys `EncryptData` encrypts masks with for
+            // another party, so the user must see which key is used and for what. The secrets of script keys key
+            // nothing on the device, so they are handed out without approval. The key is derived first, so that a
+            // path the device refuses is never shown.
+            let secret_key = derive_secret_key(account, branch, index)?;
+            let needs_approval = matches!(branch, KeyBranch::Spend | KeyBranch::DataEncryption);
+            if needs_approval && !approve_shared_secret(comm, account, branch, index, label) {
+                return Err(StatusWords::UserCancelled.into());
+            }
+            ui::SingleMessage::new("SharedSecret...").show();
+
+            let hash = shared_secret(&secret_key, &public_key, label)?;
+
+            comm.append(&[1]); // version
+            comm.append(hash.as_bytes());
+            ui::SingleMessage::new("SharedSecret... Done").show();
+            comm.reply_ok();
+        },
+        #[cfg(feature = "benchmark")]
+        Instruction::Benchmark => {
+            ui::SingleMessage::new("Benchmark...").show();
+            // P1 is the number of rounds, so that the host can time them apart from the APDU round trip by comparing
+            // two counts. Each round multiplies the base point and then the result by a key, and takes the
+            // next key from the encoded result, so that no multiplication or encoding can be skipped.
+            let rounds = get_p1(comm);
+            let mut k = RistrettoSecretKey::random();
+            let mut p = RistrettoPublicKey::default();
+            for _ in 0..rounds {
+                p = &RistrettoPublicKey::from_secret_key(&k) * &k;
+                k = RistrettoSecretKey::from_bytes(p.as_bytes())?;
+            }
+
+            comm.append(&[1]); // version
+            comm.append(p.as_bytes());
+            ui::SingleMessage::new("Benchmark... Done").show();
+            comm.reply_ok();
+        },
         Instruction::BadInstruction(val) => {
-            let mut error = "BadInstruction...! (".to_string();
-            error.push_str(&byte_to_hex(val));
-            error.push_str(&")");
-            ui::SingleMessage::new(&error).show();
+            let mut error = Message::new("BadInstruction...! (");
+            error.push_hex(&[val]).push_str(")");
+            ui::SingleMessage::new(error.as_str()).show();
             return Err(StatusWords::BadIns.into());
         },
         Instruction::Exit => {
@@ -319,6 +768,303 @@ fn handle_apdu(comm: &mut io::Comm, instruction: Instruction) -> Result<(), Repl
     Ok(())
 }
 
+// Handle one step of a streamed transaction, selected by P1:
+// - 0: start a transaction with account (8) || fee (8) || lock height (8)
+// - 1: add an input with value (8) || mask index (8) || extension degree (1) || commitment (32) || script key index (8)
+//   || script public key (32)
+// - 2: add an output with value (8) || mask index (8) || extension degree (1) || commitment (32), replying with the
+//   public key (32) of the fresh sender offset key the device generated for it
+// - 3: check that the transaction balances to the claimed excess (32) and have the user review it, then sign the kernel
+//   and release the script offset, replying with the first signature scalar (32) || public nonce (32) || script offset
+//   (32) || one more signature scalar (32) per extra blinding generator of the transaction. The transaction is kept,
+//   approved, for the steps that sign its inputs and outputs, but can no longer change.
+// - 6: review the script of the output added last, sent as the script bytes
+// - 7: review the features of the output added last, sent borsh encoded
+// - 8: review the covenant of the output added last, sent borsh encoded
+// - 9: add a one-sided payment to an external recipient with address (33) || value (8), with a fresh nonce, replying
+//   with the output's commitment (32) || public nonce `R` (32) || stealth script key `K_s` (32) || sender offset public
+//   key (32) || encrypted data, from which the host builds the output with the script `PushPubKey(R) Drop
+//   PushPubKey(K_s)`
+// - 10: add a change output with value (8) || mask index (8) || extension degree (1) || commitment (32) || script key
+//   index (8) || script bytes, where the script must be `PushPubKey` of that script key, replying with the sender
+//   offset public key (32) like step 2
+// - 11: attest the metadata of the output at position (1), counting the outputs of steps 2 and 10 in the order they
+//   were added, once the transaction is approved, replying with public nonce (32) || signature (32). The attestation is
+//   device-local, not the output's consensus metadata signature.
+// - 12: attest the script of the input at position (1), counting the inputs in the order they were added, once the
+//   transaction is approved, replying with public nonce (32) || signature (32). The attestation is device-local, not
+//   the input's consensus script signature.
+// - 13: add a one-sided payment the account received as an input, with value (8) || view key index (8) || commitment
+//   (32) || public nonce `R` (32) of the output, as found by `ScanOutputs`, whose mask and stealth script key the
+//   device derives from the view key and `R`
+// - 14: add an input recovered from its encrypted data like `DecryptData`, with encryption key selector (1), 0 or 1 as
+//   for `data_encryption_key` || extension degree (1) || commitment (32) || encrypted data || script key index (8) ||
+//   script public key (32) || the public key of the other party (32) for selector 1, so that the mask never leaves the
+//   device. One-sided payments to someone else, which `DecryptData` recovers with P1 2, are only spent by their
+//   recipient, so selector 2 is refused.
+// Steps 4 and 5, which added the script and sender offset keys apart from their input or output, are retired.
+fn handle_transaction_step(comm: &mut io::Comm, transaction: &mut Option<TransactionState>) -> Result<(), Reply> {
+    // first 5 bytes are instruction details
+    let offset = 5;
+    match get_p1(comm) {
+        0 => {
+            check_length(comm, offset + 24)?;
+            ui::SingleMessage::new("Transaction...").show();
+            let account = get_u64(comm, offset);
+            let fee = get_u64(comm, offset + 8);
+            let lock_height = get_u64(comm, offset + 16);
+            *transaction = Some(TransactionState::new(account, fee, lock_height));
+        },
+        step @ (1 | 2) => {
+            // Inputs carry their script key, while the device generates the sender offset key of an output
+            let data_length = if step == 1 { 89 } else { 49 };
+            check_length(comm, offset + data_length)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let value = get_u64(comm, offset);
+            let mask_index = get_u64(comm, offset + 8);
+            let extension_degree = get_extension_degree(comm.get(offset + 16, offset + 17)[0])?;
+            let commitment = PedersenCommitment::from_bytes(comm.get(offset + 17, offset + 49))?;
+
+            let masks = derive_commitment_masks(state.account(), mask_index, extension_degree)?;
+            let masks = &masks[..extension_degree.size()];
+            if step == 1 {
+                let key_index = get_u64(comm, offset + 49);
+                let public_key = RistrettoPublicKey::from_bytes(comm.get(offset + 57, offset + 89))?;
+                let script_key = derive_secret_key(state.account(), KeyBranch::ScriptKey, key_index)?;
+                state.add_input(&commitment, value, masks, &script_key, &public_key)?;
+            } else {
+                let sender_offset_public_key = state.add_output(&commitment, value, masks)?;
+                comm.append(&[1]); // version
+                comm.append(sender_offset_public_key.as_bytes());
+            }
+        },
+        3 => {
+            check_length(comm, offset + 32)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let excess = RistrettoPublicKey::from_bytes(comm.get(offset, offset + 32))?;
+            state.verify_excess(&excess)?;
+            let script_offset = state.script_offset()?.clone();
+            if !review_transaction(comm, state) {
+                return Err(Error::TransactionRejected.into());
+            }
+            state.approve(&excess);
+
+            let signature = state.sign_kernel(&excess)?;
+
+            // The excess is not echoed, so that the signature scalars of the highest extension degree fit the reply
+            let (first_signature, extra_signatures) = signature
+                .signatures()
+                .split_first()
+                .ok_or(Error::InvalidExtensionDegree)?;
+            comm.append(&[1]); // version
+            comm.append(first_signature.as_bytes());
+            comm.append(signature.public_nonce().as_bytes());
+            comm.append(script_offset.as_bytes());
+            for extra_signature in extra_signatures {
+                comm.append(extra_signature.as_bytes());
+            }
+            ui::SingleMessage::new("Transaction... Done").show();
+        },
+        6 => {
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let data_length = get_data_length(comm)?;
+            let script = comm.get(offset, offset + data_length);
+            let summary = state.add_script(script)?;
+            // A script the device cannot decode cannot be reviewed, so approving it is blind signing
+            if summary.is_blind() && !Settings::load().blind_signing {
+                return Err(Error::BlindSigningDisabled.into());
+            }
+        },
+        9 => {
+            check_length(comm, offset + TARI_ADDRESS_LENGTH + 8)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let address = parse_address(comm.get(offset, offset + TARI_ADDRESS_LENGTH))?;
+            let value = get_u64(comm, offset + TARI_ADDRESS_LENGTH);
+
+            // Everything that makes the output pay the address is derived here, from the random nonce of the payment
+            let nonce = RistrettoSecretKey::random();
+            let public_nonce = RistrettoPublicKey::from_secret_key(&nonce);
+            let recipient = address.public_key();
+            let script_public_key = stealth_script_public_key(&stealth_shared_secret(&nonce, recipient)?, recipient);
+            let mask = shared_secret(&nonce, recipient, SharedSecretLabel::CommitmentMask)?;
+            let encryption_key = shared_secret(&nonce, recipient, SharedSecretLabel::DataEncryptionKey)?;
+            let script = stealth_script(&public_nonce, &script_public_key);
+            let (commitment, sender_offset_public_key) = state.add_payment(address, value, &mask, &script)?;
+            let encrypted_data = EncryptedData::encrypt_data(&encryption_key, &commitment, value, &mask)?;
+
+            comm.append(&[1]); // version
+            comm.append(commitment.as_bytes());
+            comm.append(public_nonce.as_bytes());
+            comm.append(script_public_key.as_bytes());
+            comm.append(sender_offset_public_key.as_bytes());
+            comm.append(encrypted_data.as_bytes());
+        },
+        10 => {
+            check_length(comm, offset + 57)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let value = get_u64(comm, offset);
+            let mask_index = get_u64(comm, offset + 8);
+            let extension_degree = get_extension_degree(comm.get(offset + 16, offset + 17)[0])?;
+            let commitment = PedersenCommitment::from_bytes(comm.get(offset + 17, offset + 49))?;
+            let script_key_index = get_u64(comm, offset + 49);
+            let data_length = get_data_length(comm)?;
+            if data_length < 57 {
+                return Err(Error::IncorrectByteLength.into());
+            }
+            let script = comm.get(offset + 57, offset + data_length);
+
+            let masks = derive_commitment_masks(state.account(), mask_index, extension_degree)?;
+            // Change is spent as an input with its script key, so its script must push the key of that branch
+            let script_key = derive_secret_key(state.account(), KeyBranch::ScriptKey, script_key_index)?;
+            let script_public_key = RistrettoPublicKey::from_secret_key(&script_key);
+            let sender_offset_public_key = state.add_change(
+                &commitment,
+                value,
+                &masks[..extension_degree.size()],
+                &script_public_key,
+                script,
+            )?;
+
+            comm.append(&[1]); // version
+            comm.append(sender_offset_public_key.as_bytes());
+        },
+        11 => {
+            check_length(comm, offset + 1)?;
+            let state = transaction.as_ref().ok_or(Error::TransactionNotStarted)?;
+            let position = comm.get(offset, offset + 1)[0] as usize;
+            let signature = state.attest_output_metadata(position)?;
+
+            comm.append(&[1]); // version
+            comm.append(signature.get_public_nonce().as_bytes());
+            comm.append(signature.get_signature().as_bytes());
+        },
+        12 => {
+            check_length(comm, offset + 1)?;
+            let state = transaction.as_ref().ok_or(Error::TransactionNotStarted)?;
+            let position = comm.get(offset, offset + 1)[0] as usize;
+            let signature = state.attest_input_script(position)?;
+
+            comm.append(&[1]); // version
+            comm.append(signature.get_public_nonce().as_bytes());
+            comm.append(signature.get_signature().as_bytes());
+        },
+        13 => {
+            check_length(comm, offset + 80)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let value = get_u64(comm, offset);
+            let view_key_index = get_u64(comm, offset + 8);
+            let commitment = PedersenCommitment::from_bytes(comm.get(offset + 16, offset + 48))?;
+            let public_nonce = RistrettoPublicKey::from_bytes(comm.get(offset + 48, offset + 80))?;
+
+            let view_key = derive_secret_key(state.account(), KeyBranch::DataEncryption, view_key_index)?;
+            let mask = shared_secret(&view_key, &public_nonce, SharedSecretLabel::CommitmentMask)?;
+            let script_key = stealth_script_key(&view_key, &public_nonce)?;
+            let script_public_key = RistrettoPublicKey::from_secret_key(&script_key);
+            state.add_input(&commitment, value, &[mask], &script_key, &script_public_key)?;
+        },
+        14 => {
+            let data_offset = offset + 2 + 32 + ENCRYPTED_DATA_LENGTH;
+            check_length(comm, data_offset + 40)?;
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let selector = comm.get(offset, offset + 1)[0];
+            let extension_degree = get_extension_degree(comm.get(offset + 1, offset + 2)[0])?;
+            let commitment = PedersenCommitment::from_bytes(comm.get(offset + 2, offset + 34))?;
+            let encrypted_data = EncryptedData::from_bytes(comm.get(offset + 34, data_offset))?;
+            let key_index = get_u64(comm, data_offset);
+            let public_key = RistrettoPublicKey::from_bytes(comm.get(data_offset + 8, data_offset + 40))?;
+            if selector > 1 {
+                return Err(StatusWords::BadP1P2.into());
+            }
+            let (encryption_key, _) = data_encryption_key(comm, state.account(), selector, data_offset + 40)?;
+
+            let (value, mask) = encrypted_data.decrypt_data(&encryption_key, &commitment)?;
+            let masks = extend_commitment_mask(mask, extension_degree)?;
+            let script_key = derive_secret_key(state.account(), KeyBranch::ScriptKey, key_index)?;
+            state.add_input(
+                &commitment,
+                value,
+                &masks[..extension_degree.size()],
+                &script_key,
+                &public_key,
+            )?;
+        },
+        step @ (7 | 8) => {
+            let state = transaction.as_mut().ok_or(Error::TransactionNotStarted)?;
+            let data_length = get_data_length(comm)?;
+            let bytes = comm.get(offset, offset + data_length);
+
+            let approved = if step == 7 {
+                let features = OutputFeatures::from_bytes(bytes)?;
+                state.add_features(bytes)?;
+                review_output_features(comm, &features, state.lock_height())
+            } else {
+                let covenant = Covenant::from_bytes(bytes)?;
+                state.add_covenant(bytes)?;
+                review_covenant(comm, &covenant)
+            };
+            if !approved {
+                return Err(StatusWords::UserCancelled.into());
+            }
+        },
+        _ => return Err(StatusWords::BadP1P2.into()),
+    }
+    comm.reply_ok();
+    Ok(())
+}
+
+// Handle one step of an output scan, selected by P1:
+// - 0: start a scan of account (8) for the view keys with indices 0 up to the address count (1), whose public keys
+//   `GetPublicKey` returns with P1 2
+// - 1: scan records of sender public nonce (32) || commitment (32) || encrypted data, replying with owned (1) ||
+//   address index (8) || value (8) for each record. A record is 144 bytes, so only one fits in an APDU.
+fn handle_scan_step(comm: &mut io::Comm, scanner: &mut Option<OutputScanner>) -> Result<(), Reply> {
+    // first 5 bytes are instruction details
+    let offset = 5;
+    match get_p1(comm) {
+        0 => {
+            check_length(comm, offset + 9)?;
+            ui::SingleMessage::new("ScanOutputs...").show();
+            let account = get_u64(comm, offset);
+            let address_count = comm.get(offset + 8, offset + 9)[0] as usize;
+            if address_count == 0 || address_count > MAX_SCAN_ADDRESSES {
+                return Err(StatusWords::BadLen.into());
+            }
+
+            let mut keys: [RistrettoSecretKey; MAX_SCAN_ADDRESSES] = Default::default();
+            for (index, key) in keys.iter_mut().enumerate().take(address_count) {
+                *key = derive_secret_key(account, KeyBranch::DataEncryption, index as u64)?;
+            }
+            *scanner = Some(OutputScanner::new(&keys[..address_count])?);
+        },
+        1 => {
+            let scanner = scanner.as_ref().ok_or(Error::ScanNotStarted)?;
+            let data_length = get_data_length(comm)?;
+            if data_length % STEALTH_RECORD_LENGTH != 0 {
+                return Err(Error::IncorrectByteLength.into());
+            }
+
+            comm.append(&[1]); // version
+            for record in 0..data_length / STEALTH_RECORD_LENGTH {
+                let start = offset + record * STEALTH_RECORD_LENGTH;
+                let public_nonce = RistrettoPublicKey::from_bytes(comm.get(start, start + 32))?;
+                let commitment = PedersenCommitment::from_bytes(comm.get(start + 32, start + 64))?;
+                let encrypted_data = EncryptedData::from_bytes(comm.get(start + 64, start + STEALTH_RECORD_LENGTH))?;
+                let (owned, index, value) = match scanner.scan(&public_nonce, &commitment, &encrypted_data)? {
+                    Some((index, value)) => (1, index as u64, value),
+                    None => (0, 0, 0),
+                };
+                comm.append(&[owned]);
+                comm.append(&index.to_le_bytes());
+                comm.append(&value.to_le_bytes());
+            }
+            ui::SingleMessage::new("ScanOutputs... Done").show();
+        },
+        _ => return Err(StatusWords::BadP1P2.into()),
+    }
+    comm.reply_ok();
+    Ok(())
+}
+
 pub struct DomainSeparatedConsensusHasher<M>(PhantomData<M>);
 
 impl<M: DomainSeparation> DomainSeparatedConsensusHasher<M> {
@@ -376,14 +1122,258 @@ impl<D: Digest> Write for WriteHashWrapper<D> {
     }
 }
 
-/// Display global information about the app:
-/// - Current number of passwords stored
-/// - App Version
-fn display_infos() {
-    let stored_n = *b"Our test app";
-    let stored_str = unsafe { core::str::from_utf8_unchecked(&stored_n) };
-    const APP_VERSION_STR: &str = concat!(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-    ui::Menu::new(&[APP_VERSION_STR, stored_str]).show();
+// Show the network and public key of a recipient address and ask the user to approve it
+fn approve_address(comm: &mut io::Comm, address: &TariAddress) -> bool {
+    let mut message = Message::new("Send to ");
+    message
+        .push_str(address.network().as_str())
+        .push_str(" address ")
+        .push_hex(address.public_key().as_bytes());
+    approval::review_message(comm, &message) && approval::ask(comm, "Approve address?")
+}
+
+// Ask the user to approve signing an opaque challenge, which is only possible once blind signing is turned on in the
+// settings
+fn approve_blind_signing(comm: &mut io::Comm, challenge: &[u8]) -> Result<(), Reply> {
+    if !Settings::load().blind_signing {
+        return Err(Error::BlindSigningDisabled.into());
+    }
+    let mut message = Message::new("Warning: blind signing challenge ");
+    message.push_hex(challenge);
+    if approval::review_message(comm, &message) && approval::ask(comm, "Blind sign?") {
+        Ok(())
+    } else {
+        Err(StatusWords::UserCancelled.into())
+    }
+}
+
+// Ask the user to approve a shared secret of the key at `m/44'/535348'/<account>'/<branch>/<index>` under `label`
+fn approve_shared_secret(
+    comm: &mut io::Comm,
+    account: u64,
+    branch: KeyBranch,
+    index: u64,
+    label: SharedSecretLabel,
+) -> bool {
+    let mut message = Message::new("Shared secret of m/44'/535348'/");
+    message
+        .push_u64(account)
+        .push_str("'/")
+        .push_u64(branch as u64)
+        .push_str("/")
+        .push_u64(index)
+        .push_str(" for ")
+        .push_str(label.as_str());
+    approval::review_message(comm, &message) && approval::ask(comm, "Approve secret?")
+}
+
+// Ask the user to approve encrypting the mask of the output at `m/44'/535348'/<account>'/1/<index>` for the holder of
+// `public_key`
+fn approve_mask_sharing(comm: &mut io::Comm, account: u64, index: u64, public_key: &RistrettoPublicKey) -> bool {
+    let mut message = Message::new("Share mask of m/44'/535348'/");
+    message
+        .push_u64(account)
+        .push_str("'/1/")
+        .push_u64(index)
+        .push_str(" with ")
+        .push_hex(public_key.as_bytes());
+    approval::review_message(comm, &message) && approval::ask(comm, "Approve sharing?")
+}
+
+// Show the user every payment to an external recipient, the fee, the lock height and every output that is neither
+// verified change nor a payment of a balanced transaction, and ask for approval, which takes both buttons
+fn review_transaction(comm: &mut io::Comm, transaction: &TransactionState) -> bool {
+    for payment in transaction.payments() {
+        let mut message = Message::new("Send ");
+        message
+            .push_amount(payment.value)
+            .push_str(" to ")
+            .push_str(payment.address.network().as_str())
+            .push_str(" address ")
+            .push_hex(payment.address.public_key().as_bytes());
+        if !approval::review_message(comm, &message) {
+            return false;
+        }
+    }
+
+    // An inflated fee is lost to the miner, so it gets a warning of its own before the fee itself
+    if transaction.is_fee_high(Settings::load().high_fee_threshold) && !approval::review(comm, "Warning: high fee") {
+        return false;
+    }
+    // Experts also get the fee per gram, and the lock height even when the kernel has none
+    let expert_mode = Settings::load().expert_mode;
+    let mut message = Message::new("Fee ");
+    message.push_amount(transaction.fee());
+    if expert_mode {
+        message
+            .push_str(" (")
+            .push_u64(transaction.fee_per_gram())
+            .push_str(" uT/g)");
+    }
+    if !approval::review_message(comm, &message) {
+        return false;
+    }
+    if expert_mode || transaction.lock_height() > 0 {
+        let mut message = Message::new("Lock height ");
+        message.push_u64(transaction.lock_height());
+        if !approval::review_message(comm, &message) {
+            return false;
+        }
+    }
+    // Verified change stays with the account, while any other output may pay anyone, so each is shown with its script
+    for (value, summary) in transaction.unverified_outputs() {
+        let mut message = Message::new("Output ");
+        message.push_amount(value);
+        if !approval::review_message(comm, &message) || !review_script(comm, summary) {
+            return false;
+        }
+    }
+
+    approval::ask(comm, "Approve transaction?")
+}
+
+// Show what the script of an output does, asking the user to approve a script the device cannot decode
+fn review_script(comm: &mut io::Comm, summary: &ScriptSummary) -> bool {
+    let mut message = match &summary.kind {
+        ScriptKind::Standard => Message::new("Standard script"),
+        ScriptKind::PayToKey(public_key) => {
+            let mut message = Message::new("Pays to key ");
+            message.push_hex(public_key.as_bytes());
+            message
+        },
+        ScriptKind::Custom => Message::new("Custom script"),
+        ScriptKind::Unknown => Message::new("Unknown script"),
+    };
+    if let Some(lock_height) = summary.lock_height {
+        message.push_str(", time-locked until height ").push_u64(lock_height);
+    }
+    if !approval::review_message(comm, &message) {
+        return false;
+    }
+    if summary.is_blind() {
+        approval::ask(comm, "Blind sign script?")
+    } else {
+        true
+    }
+}
+
+// Highlight the features of an output that is not a plain payment, asking the user to confirm those that put the value
+// at risk
+fn review_output_features(comm: &mut io::Comm, features: &OutputFeatures, lock_height: u64) -> bool {
+    if features.is_standard() {
+        return true;
+    }
+    let mut message = Message::new("Output type ");
+    message.push_str(features.output_type.as_str());
+    if features.maturity > 0 {
+        message.push_str(", spendable from height ").push_u64(features.maturity);
+    }
+    if !approval::review_message(comm, &message) {
+        return false;
+    }
+    // The sidechain feature gets a screen of its own, as its keys are shown in full
+    if let Some(feature) = &features.sidechain_feature {
+        let message = match feature {
+            SideChainFeature::ValidatorNodeRegistration(public_key) => {
+                let mut message = Message::new("Registers validator node ");
+                message.push_hex(public_key.as_bytes());
+                message
+            },
+            SideChainFeature::CodeTemplateRegistration {
+                author_public_key,
+                name,
+                version,
+            } => {
+                let mut message = Message::new("Registers template ");
+                message
+                    .push_str(name.as_str())
+                    .push_str(" v")
+                    .push_u64(u64::from(*version))
+                    .push_str(" by ")
+                    .push_hex(author_public_key.as_bytes());
+                message
+            },
+            SideChainFeature::ConfidentialOutput(public_key) => {
+                let mut message = Message::new("Claimable on sidechain by ");
+                message.push_hex(public_key.as_bytes());
+                message
+            },
+        };
+        if !approval::review_message(comm, &message) {
+            return false;
+        }
+    }
+    if features.needs_confirmation(lock_height) {
+        approval::ask(comm, "Approve features?")
+    } else {
+        true
+    }
+}
+
+// Show the filters of a covenant on an output one screen each, in prefix order, and ask the user to confirm it, as it
+// restricts how the output can be spent
+fn review_covenant(comm: &mut io::Comm, covenant: &Covenant) -> bool {
+    if covenant.is_empty() {
+        return true;
+    }
+    for (index, filter) in covenant.filters().enumerate() {
+        let mut message = Message::new("Covenant filter ");
+        message
+            .push_u64(index as u64 + 1)
+            .push_str(": ")
+            .push_str(filter.as_str());
+        if let Filter::AbsoluteHeight(height) = filter {
+            message.push_str(" ").push_u64(height);
+        }
+        if !approval::review_message(comm, &message) {
+            return false;
+        }
+    }
+    approval::ask(comm, "Approve covenant?")
+}
+
+// Act on the home menu entry the user selected, opening the screen it shows in `menu`
+fn select_home_entry(menu: &mut HomeMenu, entry: HomeEntry) {
+    match entry {
+        HomeEntry::Version => {
+            let mut message = Message::new(NAME);
+            message.push_str(" ").push_str(VERSION);
+            menu.open_text(message);
+        },
+        HomeEntry::Settings => menu.open_settings(),
+        HomeEntry::ShowAddress => {
+            if let Ok(message) = address_message() {
+                menu.open_text(message);
+            }
+        },
+        HomeEntry::Quit => nanos_sdk::exit_app(0),
+    }
+}
+
+// Change the setting of the settings entry the user selected to its next value, and store it
+fn change_setting(entry: SettingsEntry) {
+    let mut settings = Settings::load();
+    match entry {
+        SettingsEntry::BlindSigning => settings.blind_signing = !settings.blind_signing,
+        SettingsEntry::ExpertMode => settings.expert_mode = !settings.expert_mode,
+        SettingsEntry::Network => settings.network = settings.network.next(),
+        SettingsEntry::Account => settings.default_account = (settings.default_account + 1) % SETTINGS_ACCOUNTS,
+        SettingsEntry::Timeout => settings.timeout_minutes = next_timeout_minutes(settings.timeout_minutes),
+        SettingsEntry::HighFee => settings.high_fee_threshold = next_high_fee_threshold(settings.high_fee_threshold),
+        SettingsEntry::Back => return,
+    }
+    settings.store();
+}
+
+// The address of the default account on the network chosen in the settings, i.e. its view key at index 0, which
+// `ScanOutputs` finds the one-sided payments to
+fn address_message() -> Result<Message, Reply> {
+    let settings = Settings::load();
+    let view_key = derive_secret_key(settings.default_account, KeyBranch::DataEncryption, 0)?;
+    let address = TariAddress::new(settings.network, RistrettoPublicKey::from_secret_key(&view_key));
+    let mut message = Message::new(address.network().as_str());
+    message.push_str(" address ").push_hex(&address.to_bytes());
+    Ok(message)
 }
 
 #[derive(Clone, Copy)]
@@ -447,7 +1437,13 @@ impl<const N: usize> ArrayString<N> {
 }
 
 /// Allocator heap size
-const HEAP_SIZE: usize = 1024 * 26;
+/// - Key, signature and commitment arithmetic and the screen text are allocation free, so the heap only backs
+///   dependencies that need `alloc`.
+/// - The tests compare against `tari_crypto`, which allocates its generators and multiscalar multiplication tables.
+#[cfg(not(test))]
+const HEAP_SIZE: usize = 1024 * 8;
+#[cfg(test)]
+const HEAP_SIZE: usize = 1024 * 16;
 
 /// Statically allocated heap memory
 static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
@@ -481,3 +1477,29 @@ unsafe impl critical_section::Impl for MyCriticalSection {
         // nothing, it's all good, don't worry bout it
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    #[test]
+    fn bip32_paths_are_in_range() {
+        const HARDENED: u64 = 0x8000_0000;
+        let path = bip32_path(HARDENED - 1, KeyBranch::ScriptKey, HARDENED - 1).unwrap_or_default();
+        assert_eq!(path, [
+            44 | 0x8000_0000,
+            535348 | 0x8000_0000,
+            0xffff_ffff,
+            3,
+            0x7fff_ffff
+        ]);
+        // An account or index the path cannot hold as shown is rejected rather than truncated or hardened
+        assert_eq!(bip32_path(HARDENED, KeyBranch::Spend, 0).is_err(), true);
+        assert_eq!(bip32_path((1 << 32) + 5, KeyBranch::Spend, 0).is_err(), true);
+        assert_eq!(bip32_path(0, KeyBranch::Spend, HARDENED).is_err(), true);
+        assert_eq!(derive_secret_key((1 << 32) + 5, KeyBranch::Spend, 0).is_err(), true);
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! The home menu
+//! The home screen is a menu the user steps through with the left and right buttons and selects from with both. It is
+//! driven by the button events of the main event loop rather than a blocking `ui::Menu`, so that APDUs are still
+//! handled while it is shown.
+//! - The screens its entries open, the settings and the texts of the version and the address, are driven the same way,
+//!   so that the inactivity timeout also returns from them to the home screen.
+//! - Errors are reported the same way: the status word is replied right away, and the error is only shown over the menu
+//!   on the next idle tick, until any button dismisses it.
+
+use nanos_ui::ui;
+
+use crate::{
+    display::{page, page_count, Message},
+    settings::Settings,
+};
+
+/// The entries of the home menu, in the order they are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HomeEntry {
+    Version,
+    Settings,
+    ShowAddress,
+    Quit,
+}
+
+const HOME_ENTRIES: [HomeEntry; 4] = [
+    HomeEntry::Version,
+    HomeEntry::Settings,
+    HomeEntry::ShowAddress,
+    HomeEntry::Quit,
+];
+
+impl HomeEntry {
+    /// The label of the entry, as shown to the user
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Version => "Version",
+            Self::Settings => "Settings",
+            Self::ShowAddress => "Show address",
+            Self::Quit => "Quit",
+        }
+    }
+}
+
+/// The entries of the settings screen, in the order they are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsEntry {
+    BlindSigning,
+    ExpertMode,
+    Network,
+    Account,
+    Timeout,
+    HighFee,
+    Back,
+}
+
+const SETTINGS_ENTRIES: [SettingsEntry; 7] = [
+    SettingsEntry::BlindSigning,
+    SettingsEntry::ExpertMode,
+    SettingsEntry::Network,
+    SettingsEntry::Account,
+    SettingsEntry::Timeout,
+    SettingsEntry::HighFee,
+    SettingsEntry::Back,
+];
+
+impl SettingsEntry {
+    /// The label of the entry with the value of its setting in `settings`, as shown to the user
+    pub fn label(self, settings: &Settings) -> Message {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match self {
+            Self::BlindSigning => {
+                let mut label = Message::new("Blind signing: ");
+                label.push_str(on_off(settings.blind_signing));
+                label
+            },
+            Self::ExpertMode => {
+                let mut label = Message::new("Expert mode: ");
+                label.push_str(on_off(settings.expert_mode));
+                label
+            },
+            Self::Network => {
+                let mut label = Message::new("Network: ");
+                label.push_str(settings.network.as_str());
+                label
+            },
+            Self::Account => {
+                let mut label = Message::new("Account: ");
+                label.push_u64(settings.default_account);
+                label
+            },
+            Self::Timeout => {
+                let mut label = Message::new("Timeout: ");
+                label.push_u64(u64::from(settings.timeout_minutes)).push_str(" min");
+                label
+            },
+            Self::HighFee => {
+                let mut label = Message::new("High fee: ");
+                label.push_u64(u64::from(settings.high_fee_threshold)).push_str(" uT/g");
+                label
+            },
+            Self::Back => Message::new("Back"),
+        }
+    }
+}
+
+/// An entry the user selected with both buttons, for the event loop to act on
+pub enum Selection {
+    Home(HomeEntry),
+    Setting(SettingsEntry),
+}
+
+// The screen the menu shows
+#[derive(Default)]
+enum Screen {
+    #[default]
+    Home,
+    // A text the user pages through with the left and right buttons, and leaves with both
+    Text {
+        text: Message,
+        page: usize,
+    },
+    // The settings, one entry at a time like the home menu
+    Settings {
+        index: usize,
+    },
+}
+
+/// The home menu, which wraps around at either end, and the screens opened from it
+#[derive(Default)]
+pub struct HomeMenu {
+    index: usize,
+    screen: Screen,
+    /// The status word of an error to show on the next idle tick
+    pending_error: Option<u16>,
+    /// Whether an error is shown instead of the menu
+    showing_error: bool,
+}
+
+impl HomeMenu {
+    /// The entry currently shown
+    pub fn entry(&self) -> HomeEntry {
+        HOME_ENTRIES[self.index]
+    }
+
+    /// Step to the next entry, or page of a text
+    pub fn next(&mut self) {
+        match &mut self.screen {
+            Screen::Home => self.index = (self.index + 1) % HOME_ENTRIES.len(),
+            Screen::Text { text, page } => *page = (*page + 1).min(page_count(text.as_str()) - 1),
+            Screen::Settings { index } => *index = (*index + 1) % SETTINGS_ENTRIES.len(),
+        }
+    }
+
+    /// Step to the previous entry, or page of a text
+    pub fn previous(&mut self) {
+        match &mut self.screen {
+            Screen::Home => self.index = (self.index + HOME_ENTRIES.len() - 1) % HOME_ENTRIES.len(),
+            Screen::Text { page, .. } => *page = page.saturating_sub(1),
+            Screen::Settings { index } => *index = (*index + SETTINGS_ENTRIES.len() - 1) % SETTINGS_ENTRIES.len(),
+        }
+    }
+
+    /// Select the entry shown, returning it unless it only leaves a text or the settings for the home menu
+    pub fn select(&mut self) -> Option<Selection> {
+        match self.screen {
+            Screen::Home => Some(Selection::Home(self.entry())),
+            Screen::Settings { index } if SETTINGS_ENTRIES[index] != SettingsEntry::Back => {
+                Some(Selection::Setting(SETTINGS_ENTRIES[index]))
+            },
+            Screen::Text { .. } | Screen::Settings { .. } => {
+                self.screen = Screen::Home;
+                None
+            },
+        }
+    }
+
+    /// Open the settings at their first entry
+    pub fn open_settings(&mut self) {
+        self.screen = Screen::Settings { index: 0 };
+    }
+
+    /// Open `text` at its first page
+    pub fn open_text(&mut self, text: Message) {
+        self.screen = Screen::Text { text, page: 0 };
+    }
+
+    /// Show the entry or page currently selected
+    pub fn show(&self) {
+        match &self.screen {
+            Screen::Home => ui::SingleMessage::new(self.entry().as_str()).show(),
+            Screen::Text { text, page: current } => ui::SingleMessage::new(page(text.as_str(), *current)).show(),
+            Screen::Settings { index } => {
+                ui::SingleMessage::new(SETTINGS_ENTRIES[*index].label(&Settings::load()).as_str()).show()
+            },
+        }
+    }
+
+    /// Report an error with `status_word`, to be shown on the next idle tick
+    /// - Only the latest error is kept, as the host has already been told about every one of them.
+    pub fn report_error(&mut self, status_word: u16) {
+        self.pending_error = Some(status_word);
+    }
+
+    /// Show the pending error, if any, without waiting for a button
+    pub fn tick(&mut self) {
+        if let Some(status_word) = self.pending_error.take() {
+            let mut message = Message::new("Error ");
+            message.push_hex(&status_word.to_be_bytes());
+            ui::SingleMessage::new(message.as_str()).show();
+            self.showing_error = true;
+        }
+    }
+
+    /// Dismiss the error shown, returning whether there was one, so that the button press is not also taken as a menu
+    /// action
+    pub fn dismiss_error(&mut self) -> bool {
+        if self.showing_error {
+            self.showing_error = false;
+            self.show();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,450 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Output features decoding
+//! The features of an output decide what kind of output it is and when it can be spent. They are borsh encoded as
+//! `version (1) || output type (1) || maturity (8) || coinbase extra (4 + n) || sidechain feature (1 [+ m]) || range
+//! proof type (1)`, as in `tari_core`'s `OutputFeatures`.
+//! - The sidechain feature is decoded far enough to show what it registers and for whom, while its signatures, build
+//!   info and binary are skipped, as the device cannot check them.
+
+use borsh::BorshDeserialize;
+
+use crate::{errors::Error, ristretto_keys::RistrettoPublicKey};
+
+/// Maturities more than this many blocks, about a day, past the transaction's lock height need extra confirmation
+pub const LONG_MATURITY: u64 = 720;
+
+// `OutputFeaturesVersion::V0`
+const OUTPUT_FEATURES_VERSION: u8 = 0;
+
+// The borsh encoded sizes of a public key and a Schnorr signature, each key being length prefixed
+const PUBLIC_KEY_SIZE: usize = 4 + 32;
+const SIGNATURE_SIZE: usize = 2 * PUBLIC_KEY_SIZE;
+
+// The bounds of `tari_core`'s `MaxSizeString` and `MaxSizeBytes` fields of a code template registration
+const MAX_TEMPLATE_NAME_LENGTH: usize = 32;
+const MAX_URL_LENGTH: usize = 255;
+const MAX_HASH_LENGTH: usize = 32;
+
+/// The kinds of outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputType {
+    Standard = 0,
+    Coinbase = 1,
+    Burn = 2,
+    ValidatorNodeRegistration = 3,
+    CodeTemplateRegistration = 4,
+}
+
+impl OutputType {
+    fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(Self::Standard),
+            1 => Ok(Self::Coinbase),
+            2 => Ok(Self::Burn),
+            3 => Ok(Self::ValidatorNodeRegistration),
+            4 => Ok(Self::CodeTemplateRegistration),
+            _ => Err(Error::InvalidOutputFeatures),
+        }
+    }
+
+    /// The name of the output type, as shown to the user
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Coinbase => "coinbase",
+            Self::Burn => "burn",
+            Self::ValidatorNodeRegistration => "validator node registration",
+            Self::CodeTemplateRegistration => "code template registration",
+        }
+    }
+}
+
+/// The name of a code template, which is printable ASCII
+pub struct TemplateName {
+    bytes: [u8; MAX_TEMPLATE_NAME_LENGTH],
+    length: usize,
+}
+
+impl TemplateName {
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.length]).unwrap_or_default()
+    }
+}
+
+/// The decoded sidechain feature of an output
+pub enum SideChainFeature {
+    /// Registers the validator node with this public key
+    ValidatorNodeRegistration(RistrettoPublicKey),
+    /// Registers a code template, signed by the author with this public key
+    CodeTemplateRegistration {
+        author_public_key: RistrettoPublicKey,
+        name: TemplateName,
+        version: u16,
+    },
+    /// Makes the output claimable on the sidechain by the holder of this public key
+    ConfidentialOutput(RistrettoPublicKey),
+}
+
+impl SideChainFeature {
+    fn read(buf: &mut &[u8]) -> Result<Self, Error> {
+        match read::<u8>(buf)? {
+            0 => {
+                // The node's public key, then its signature
+                let public_key = read_public_key(buf)?;
+                skip(buf, SIGNATURE_SIZE)?;
+                Ok(Self::ValidatorNodeRegistration(public_key))
+            },
+            1 => {
+                let author_public_key = read_public_key(buf)?;
+                skip(buf, SIGNATURE_SIZE)?;
+                let name = read_template_name(buf)?;
+                let version = read(buf)?;
+                // `TemplateType::Wasm` has an ABI version, `Flow` and `Manifest` have no data
+                match read::<u8>(buf)? {
+                    0 => skip(buf, 2)?,
+                    1 | 2 => {},
+                    _ => return Err(Error::InvalidOutputFeatures),
+                }
+                // The build info's repository URL and commit hash, then the binary's hash and URL
+                skip_bytes(buf, MAX_URL_LENGTH)?;
+                skip_bytes(buf, MAX_HASH_LENGTH)?;
+                skip_bytes(buf, MAX_HASH_LENGTH)?;
+                skip_bytes(buf, MAX_URL_LENGTH)?;
+                Ok(Self::CodeTemplateRegistration {
+                    author_public_key,
+                    name,
+                    version,
+                })
+            },
+            2 => Ok(Self::ConfidentialOutput(read_public_key(buf)?)),
+            _ => Err(Error::InvalidOutputFeatures),
+        }
+    }
+}
+
+/// The decoded features of an output
+pub struct OutputFeatures {
+    pub output_type: OutputType,
+    /// The height before which the output cannot be spent
+    pub maturity: u64,
+    pub sidechain_feature: Option<SideChainFeature>,
+}
+
+impl OutputFeatures {
+    /// Decode the borsh encoded features `bytes`
+    /// - Returns `Error::InvalidOutputFeatures` for an unknown version or enum value, or if the bytes are cut short or
+    ///   have trailing data.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        let buf = &mut bytes;
+        if read::<u8>(buf)? != OUTPUT_FEATURES_VERSION {
+            return Err(Error::InvalidOutputFeatures);
+        }
+        let output_type = OutputType::from_byte(read(buf)?)?;
+        let maturity = read(buf)?;
+        // The coinbase extra is free-form data for the miner, so it is skipped
+        let coinbase_extra_length = read::<u32>(buf)? as usize;
+        skip(buf, coinbase_extra_length)?;
+        let sidechain_feature = match read::<u8>(buf)? {
+            0 => None,
+            1 => Some(SideChainFeature::read(buf)?),
+            _ => return Err(Error::InvalidOutputFeatures),
+        };
+        // `RangeProofType::BulletProofPlus` or `RangeProofType::RevealedValue`
+        if read::<u8>(buf)? > 1 || !buf.is_empty() {
+            return Err(Error::InvalidOutputFeatures);
+        }
+        Ok(Self {
+            output_type,
+            maturity,
+            sidechain_feature,
+        })
+    }
+
+    /// Whether these are the features of a plain payment
+    pub fn is_standard(&self) -> bool {
+        self.output_type == OutputType::Standard && self.maturity == 0 && self.sidechain_feature.is_none()
+    }
+
+    /// Whether the user must confirm these features before the transaction is signed: burns, which destroy the value,
+    /// maturities that lock the value up well past `lock_height`, and sidechain features, whose signatures cannot be
+    /// checked
+    pub fn needs_confirmation(&self, lock_height: u64) -> bool {
+        self.output_type == OutputType::Burn ||
+            self.maturity > lock_height.saturating_add(LONG_MATURITY) ||
+            self.sidechain_feature.is_some()
+    }
+}
+
+// Read a borsh encoded public key from the front of `buf`, which is length prefixed
+fn read_public_key(buf: &mut &[u8]) -> Result<RistrettoPublicKey, Error> {
+    let bytes = read_bytes(buf, 32)?;
+    if bytes.len() != 32 {
+        return Err(Error::InvalidOutputFeatures);
+    }
+    RistrettoPublicKey::from_bytes(bytes).map_err(|_| Error::InvalidOutputFeatures)
+}
+
+// Read a template name, which must be printable ASCII so that it can be shown
+fn read_template_name(buf: &mut &[u8]) -> Result<TemplateName, Error> {
+    let bytes = read_bytes(buf, MAX_TEMPLATE_NAME_LENGTH)?;
+    if !bytes.iter().all(|byte| byte.is_ascii_graphic() || *byte == b' ') {
+        return Err(Error::InvalidOutputFeatures);
+    }
+    let mut name = TemplateName {
+        bytes: [0u8; MAX_TEMPLATE_NAME_LENGTH],
+        length: bytes.len(),
+    };
+    name.bytes[..bytes.len()].copy_from_slice(bytes);
+    Ok(name)
+}
+
+// Read length prefixed bytes of at most `max_length` from the front of `buf`
+fn read_bytes<'a>(buf: &mut &'a [u8], max_length: usize) -> Result<&'a [u8], Error> {
+    let length = read::<u32>(buf)? as usize;
+    if length > max_length || length > buf.len() {
+        return Err(Error::InvalidOutputFeatures);
+    }
+    let (bytes, rest) = buf.split_at(length);
+    *buf = rest;
+    Ok(bytes)
+}
+
+// Skip length prefixed bytes of at most `max_length`
+fn skip_bytes(buf: &mut &[u8], max_length: usize) -> Result<(), Error> {
+    read_bytes(buf, max_length).map(|_| ())
+}
+
+// Skip `length` bytes
+fn skip(buf: &mut &[u8], length: usize) -> Result<(), Error> {
+    if length > buf.len() {
+        return Err(Error::InvalidOutputFeatures);
+    }
+    *buf = &buf[length..];
+    Ok(())
+}
+
+// Read a borsh encoded value from the front of `buf`
+fn read<T: BorshDeserialize>(buf: &mut &[u8]) -> Result<T, Error> {
+    T::deserialize(buf).map_err(|_| Error::InvalidOutputFeatures)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use curve25519_dalek::constants::RISTRETTO_BASEPOINT_COMPRESSED;
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    // Append `bytes` with their u32 length, as borsh writes a `Vec<u8>`, a `String` and a public key
+    fn push_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+        buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(bytes);
+    }
+
+    // The borsh encoding of `OutputFeatures` as `tari_core` writes it
+    fn features(output_type: u8, maturity: u64, coinbase_extra: &[u8], sidechain_feature: Option<&[u8]>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.push(0); // version
+        buf.push(output_type);
+        buf.extend_from_slice(&maturity.to_le_bytes());
+        push_bytes(&mut buf, coinbase_extra);
+        match sidechain_feature {
+            Some(feature) => {
+                buf.push(1);
+                buf.extend_from_slice(feature);
+            },
+            None => buf.push(0),
+        }
+        buf.push(0); // range proof type
+        buf
+    }
+
+    // A public key and a signature by it, which is not checked
+    fn push_signed_key(buf: &mut Vec<u8>) {
+        push_bytes(buf, RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
+        push_bytes(buf, &[0; 32]); // public nonce
+        push_bytes(buf, &[0; 32]); // signature
+    }
+
+    // `SideChainFeature::CodeTemplateRegistration` with the template type `template_type` and its data
+    fn code_template(name: &[u8], template_type: &[u8], url: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.push(1);
+        push_signed_key(&mut buf);
+        push_bytes(&mut buf, name);
+        buf.extend_from_slice(&2u16.to_le_bytes()); // template version
+        buf.extend_from_slice(template_type);
+        push_bytes(&mut buf, url); // repository URL
+        push_bytes(&mut buf, &[0x11; 20]); // commit hash
+        push_bytes(&mut buf, &[0x22; 32]); // binary hash
+        push_bytes(&mut buf, url); // binary URL
+        buf
+    }
+
+    fn validator_node() -> Vec<u8> {
+        let mut buf = Vec::from([0u8]);
+        push_signed_key(&mut buf);
+        buf
+    }
+
+    fn confidential_output() -> Vec<u8> {
+        let mut buf = Vec::from([2u8]);
+        push_bytes(&mut buf, RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
+        buf
+    }
+
+    // Every vector must be rejected once cut short by any number of bytes, or with a trailing byte
+    fn rejects_truncated_and_oversized(bytes: &[u8]) -> bool {
+        let mut oversized = Vec::from(bytes);
+        oversized.push(0);
+        (0..bytes.len()).all(|length| OutputFeatures::from_bytes(&bytes[..length]).is_err()) &&
+            OutputFeatures::from_bytes(&oversized).is_err()
+    }
+
+    #[test]
+    fn standard_features() {
+        let bytes = features(0, 0, &[], None);
+        assert_eq!(bytes.as_slice(), &[0u8; 16][..]);
+        let features = OutputFeatures::from_bytes(&bytes).unwrap();
+        assert_eq!(features.is_standard(), true);
+        assert_eq!(features.needs_confirmation(0), false);
+        assert_eq!(rejects_truncated_and_oversized(&bytes), true);
+    }
+
+    #[test]
+    fn output_types() {
+        for (byte, output_type) in [
+            (0, OutputType::Standard),
+            (1, OutputType::Coinbase),
+            (2, OutputType::Burn),
+            (3, OutputType::ValidatorNodeRegistration),
+            (4, OutputType::CodeTemplateRegistration),
+        ] {
+            let features = OutputFeatures::from_bytes(&features(byte, 0, &[], None)).unwrap();
+            assert_eq!(features.output_type, output_type);
+            assert_eq!(features.needs_confirmation(0), output_type == OutputType::Burn);
+        }
+        assert_eq!(OutputFeatures::from_bytes(&features(5, 0, &[], None)).is_err(), true);
+    }
+
+    #[test]
+    fn maturity_and_coinbase_extra() {
+        let bytes = features(1, LONG_MATURITY + 1, b"pool", None);
+        let features = OutputFeatures::from_bytes(&bytes).unwrap();
+        assert_eq!(features.maturity, LONG_MATURITY + 1);
+        assert_eq!(features.is_standard(), false);
+        assert_eq!(features.needs_confirmation(0), true);
+        assert_eq!(features.needs_confirmation(1), false);
+        assert_eq!(rejects_truncated_and_oversized(&bytes), true);
+    }
+
+    #[test]
+    fn sidechain_features() {
+        let bytes = features(3, 0, &[], Some(&validator_node()));
+        match OutputFeatures::from_bytes(&bytes).unwrap().sidechain_feature {
+            Some(SideChainFeature::ValidatorNodeRegistration(public_key)) => {
+                assert_eq!(public_key.as_bytes(), RISTRETTO_BASEPOINT_COMPRESSED.as_bytes())
+            },
+            _ => assert_eq!(true, false),
+        }
+        assert_eq!(rejects_truncated_and_oversized(&bytes), true);
+
+        let bytes = features(0, 0, &[], Some(&confidential_output()));
+        match OutputFeatures::from_bytes(&bytes).unwrap().sidechain_feature {
+            Some(SideChainFeature::ConfidentialOutput(public_key)) => {
+                assert_eq!(public_key.as_bytes(), RISTRETTO_BASEPOINT_COMPRESSED.as_bytes())
+            },
+            _ => assert_eq!(true, false),
+        }
+        assert_eq!(rejects_truncated_and_oversized(&bytes), true);
+
+        // `TemplateType::Wasm` with its ABI version, `Flow` and `Manifest`
+        for template_type in [&[0u8, 1, 0][..], &[1], &[2]] {
+            let bytes = features(
+                4,
+                0,
+                &[],
+                Some(&code_template(b"counter", template_type, b"https://tari.com")),
+            );
+            match OutputFeatures::from_bytes(&bytes).unwrap().sidechain_feature {
+                Some(SideChainFeature::CodeTemplateRegistration {
+                    author_public_key,
+                    name,
+                    version,
+                }) => {
+                    assert_eq!(author_public_key.as_bytes(), RISTRETTO_BASEPOINT_COMPRESSED.as_bytes());
+                    assert_eq!(name.as_str(), "counter");
+                    assert_eq!(version, 2);
+                },
+                _ => assert_eq!(true, false),
+            }
+            assert_eq!(rejects_truncated_and_oversized(&bytes), true);
+        }
+    }
+
+    #[test]
+    fn invalid_features() {
+        // An unknown version, sidechain feature tag, sidechain feature, template type or range proof type
+        let mut bytes = features(0, 0, &[], None);
+        bytes[0] = 1;
+        assert_eq!(OutputFeatures::from_bytes(&bytes).is_err(), true);
+        let mut bytes = features(0, 0, &[], None);
+        bytes[14] = 2;
+        assert_eq!(OutputFeatures::from_bytes(&bytes).is_err(), true);
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(0, 0, &[], Some(&[3]))).is_err(),
+            true
+        );
+        let template = code_template(b"counter", &[3], b"https://tari.com");
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(4, 0, &[], Some(&template))).is_err(),
+            true
+        );
+        let mut bytes = features(0, 0, &[], None);
+        bytes[15] = 1; // `RangeProofType::RevealedValue`
+        assert_eq!(OutputFeatures::from_bytes(&bytes).is_ok(), true);
+        bytes[15] = 2;
+        assert_eq!(OutputFeatures::from_bytes(&bytes).is_err(), true);
+
+        // A coinbase extra length that runs into the fields after it
+        let mut bytes = features(0, 0, &[], None);
+        bytes[10] = 1;
+        assert_eq!(OutputFeatures::from_bytes(&bytes).is_err(), true);
+
+        // A public key that is not 32 bytes, or not a valid encoding
+        let mut feature = Vec::from([2u8]);
+        push_bytes(&mut feature, &[0; 33]);
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(0, 0, &[], Some(&feature))).is_err(),
+            true
+        );
+        let mut feature = Vec::from([2u8]);
+        push_bytes(&mut feature, &[0xff; 32]);
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(0, 0, &[], Some(&feature))).is_err(),
+            true
+        );
+
+        // Template names and URLs over their bounds, and names that cannot be shown
+        let template = code_template(&[b'a'; MAX_TEMPLATE_NAME_LENGTH + 1], &[1], b"https://tari.com");
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(4, 0, &[], Some(&template))).is_err(),
+            true
+        );
+        let template = code_template(&[b'a'; MAX_TEMPLATE_NAME_LENGTH], &[1], &[b'a'; MAX_URL_LENGTH + 1]);
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(4, 0, &[], Some(&template))).is_err(),
+            true
+        );
+        let template = code_template(b"count\ner", &[1], b"https://tari.com");
+        assert_eq!(
+            OutputFeatures::from_bytes(&features(4, 0, &[], Some(&template))).is_err(),
+            true
+        );
+    }
+}
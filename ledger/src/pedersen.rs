@@ -0,0 +1,210 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Pedersen commitments on the ristretto255 curve
+//! A commitment to a value `v` with blinding factor `k` is `C = k.G + v.H`, where `G` is the Ristretto base point and
+//! `H` is the first of tari_crypto's "nothing up my sleeve" points. These are the same generators used by tari_crypto's
+//! `ExtendedPedersenCommitmentFactory::default()`, so commitments made here are byte-for-byte identical.
+//! - An extended commitment `C = k_0.G_0 + k_1.G_1 + ... + v.H` has one blinding factor per blinding generator, where
+//!   `G_0 = G` and the extra generators are the following "nothing up my sleeve" points, as in tari_crypto's
+//!   `ExtendedPedersenCommitmentFactory::new_with_extension_degree`.
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+
+use crate::{
+    errors::Error,
+    ristretto_keys::{mul_base_and_add, RistrettoPublicKey, RistrettoSecretKey},
+};
+
+// The first "nothing up my sleeve" points of tari_crypto's `RISTRETTO_NUMS_POINTS_COMPRESSED`: `H`, followed by the
+// extra blinding generators. `build.rs` writes them out from tari_crypto at build time, which leaves it out of the app.
+const RISTRETTO_NUMS_POINTS_COMPRESSED: [CompressedRistretto; MAX_EXTENSION_DEGREE] =
+    include!(concat!(env!("OUT_DIR"), "/nums_points.rs"));
+
+/// A Pedersen commitment, which is simply a point on the ristretto255 curve
+#[derive(Clone, PartialEq, Eq)]
+pub struct PedersenCommitment(RistrettoPublicKey);
+
+impl PedersenCommitment {
+    /// Create a commitment from its compressed byte representation
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(Self(RistrettoPublicKey::from_bytes(bytes)?))
+    }
+
+    /// Return the commitment as a public key
+    pub fn as_public_key(&self) -> &RistrettoPublicKey {
+        &self.0
+    }
+
+    /// Return the little-endian byte array representation of the compressed commitment
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// The largest supported number of blinding generators
+pub const MAX_EXTENSION_DEGREE: usize = 6;
+
+/// The number of blinding generators of an extended commitment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtensionDegree {
+    /// Default Pedersen commitment, `C = k.G + v.H`
+    DefaultPedersen = 1,
+    /// Pedersen commitment extended with one extra blinding generator
+    AddOneBasePoint = 2,
+    /// Pedersen commitment extended with two extra blinding generators
+    AddTwoBasePoints = 3,
+    /// Pedersen commitment extended with three extra blinding generators
+    AddThreeBasePoints = 4,
+    /// Pedersen commitment extended with four extra blinding generators
+    AddFourBasePoints = 5,
+    /// Pedersen commitment extended with five extra blinding generators
+    AddFiveBasePoints = 6,
+}
+
+impl ExtensionDegree {
+    /// Return the extension degree with `size` blinding generators
+    pub fn try_from_size(size: usize) -> Result<Self, Error> {
+        match size {
+            1 => Ok(Self::DefaultPedersen),
+            2 => Ok(Self::AddOneBasePoint),
+            3 => Ok(Self::AddTwoBasePoints),
+            4 => Ok(Self::AddThreeBasePoints),
+            5 => Ok(Self::AddFourBasePoints),
+            6 => Ok(Self::AddFiveBasePoints),
+            _ => Err(Error::InvalidExtensionDegree),
+        }
+    }
+
+    /// The number of blinding generators
+    pub fn size(self) -> usize {
+        self as usize
+    }
+}
+
+/// Creates Pedersen commitments `C = k.G + v.H`, or extended commitments `C = k_0.G_0 + k_1.G_1 + ... + v.H`
+pub struct PedersenCommitmentFactory {
+    /// Base for the committed value
+    h_base: CompressedRistretto,
+    /// The number of blinding generators
+    extension_degree: ExtensionDegree,
+}
+
+impl Default for PedersenCommitmentFactory {
+    fn default() -> Self {
+        Self::new_with_extension_degree(ExtensionDegree::DefaultPedersen)
+    }
+}
+
+impl PedersenCommitmentFactory {
+    /// Create a factory for extended commitments with `extension_degree` blinding generators
+    pub fn new_with_extension_degree(extension_degree: ExtensionDegree) -> Self {
+        Self {
+            h_base: RISTRETTO_NUMS_POINTS_COMPRESSED[0],
+            extension_degree,
+        }
+    }
+
+    /// Create a commitment to the value `v` with blinding factor `k`
+    pub fn commit(&self, k: &RistrettoSecretKey, v: &RistrettoSecretKey) -> PedersenCommitment {
+        PedersenCommitment(mul_base_and_add(&k.0, &[(&v.0, &self.h_base)]))
+    }
+
+    /// Create a commitment to the integer `value` with blinding factor `k`
+    pub fn commit_value(&self, k: &RistrettoSecretKey, value: u64) -> PedersenCommitment {
+        self.commit(k, &RistrettoSecretKey::from(value))
+    }
+
+    /// Create an extended commitment to the integer `value` with one blinding factor per blinding generator
+    pub fn commit_value_extended(&self, k_vec: &[RistrettoSecretKey], value: u64) -> Result<PedersenCommitment, Error> {
+        if k_vec.len() != self.extension_degree.size() {
+            return Err(Error::InvalidExtensionDegree);
+        }
+        let v = RistrettoSecretKey::from(value);
+        // The value term followed by one term per extra blinding generator, so that the sum is taken in one go
+        let mut terms = [(&v.0, &self.h_base); MAX_EXTENSION_DEGREE];
+        for (term, (k, g)) in terms[1..]
+            .iter_mut()
+            .zip(k_vec[1..].iter().zip(&RISTRETTO_NUMS_POINTS_COMPRESSED[1..]))
+        {
+            *term = (&k.0, g);
+        }
+        Ok(PedersenCommitment(mul_base_and_add(&k_vec[0].0, &terms[..k_vec.len()])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use tari_crypto::{
+        commitment::{
+            ExtendedHomomorphicCommitmentFactory,
+            ExtensionDegree as TariExtensionDegree,
+            HomomorphicCommitmentFactory,
+        },
+        ristretto::{
+            pedersen::extended_commitment_factory::ExtendedPedersenCommitmentFactory,
+            RistrettoSecretKey as TariSecretKey,
+        },
+        tari_utilities::ByteArray,
+    };
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    const VALUES: [u64; 4] = [0, 1, 123_456_789, u64::MAX];
+
+    // The same blinding factors in both implementations
+    fn masks(
+        n: usize,
+    ) -> (
+        [RistrettoSecretKey; MAX_EXTENSION_DEGREE],
+        [TariSecretKey; MAX_EXTENSION_DEGREE],
+    ) {
+        let bytes = |i: usize| [0x11 * (i as u8 + 1); 32];
+        (
+            core::array::from_fn(|i| RistrettoSecretKey::from_bytes(&bytes(i + n)).unwrap()),
+            core::array::from_fn(|i| TariSecretKey::from_bytes(&bytes(i + n)).unwrap()),
+        )
+    }
+
+    #[test]
+    fn commitments_match_tari_crypto() {
+        let factory = PedersenCommitmentFactory::default();
+        let tari_factory = ExtendedPedersenCommitmentFactory::default();
+        for (n, value) in VALUES.into_iter().enumerate() {
+            let (k, tari_k) = masks(n);
+            assert_eq!(
+                factory.commit_value(&k[0], value).as_bytes(),
+                tari_factory.commit_value(&tari_k[0], value).as_bytes()
+            );
+            assert_eq!(
+                factory.commit(&k[0], &k[1]).as_bytes(),
+                tari_factory.commit(&tari_k[0], &tari_k[1]).as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn extended_commitments_match_tari_crypto() {
+        for size in 1..=MAX_EXTENSION_DEGREE {
+            let factory =
+                PedersenCommitmentFactory::new_with_extension_degree(ExtensionDegree::try_from_size(size).unwrap());
+            let tari_factory = ExtendedPedersenCommitmentFactory::new_with_extension_degree(
+                TariExtensionDegree::try_from_size(size).unwrap(),
+            )
+            .unwrap();
+            for (n, value) in VALUES.into_iter().enumerate() {
+                let (k, tari_k) = masks(n);
+                let commitment = factory.commit_value_extended(&k[..size], value).unwrap();
+                let expected = tari_factory.commit_value_extended(&tari_k[..size], value).unwrap();
+                assert_eq!(commitment.as_bytes(), expected.as_bytes());
+            }
+            // Exactly one blinding factor per generator
+            let (k, _) = masks(0);
+            assert_eq!(factory.commit_value_extended(&k[..size - 1], 1).is_err(), true);
+        }
+        assert_eq!(ExtensionDegree::try_from_size(0).is_err(), true);
+        assert_eq!(ExtensionDegree::try_from_size(MAX_EXTENSION_DEGREE + 1).is_err(), true);
+    }
+}
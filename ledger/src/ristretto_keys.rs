@@ -5,6 +5,7 @@
 
 use core::{
     borrow::Borrow,
+    cmp::Ordering,
     fmt,
     ops::{Add, Mul, Sub},
 };
@@ -25,6 +26,14 @@ pub struct RistrettoSecretKey(pub(crate) Scalar);
 
 const SCALAR_LENGTH: usize = 32;
 
+//----------------------------------   RistrettoSecretKey Drop ---------------------------------------------//
+// Overwrite the scalar when the key goes out of scope, so that a wiped session leaves no keys behind in RAM
+impl Drop for RistrettoSecretKey {
+    fn drop(&mut self) {
+        unsafe { core::ptr::write_volatile(&mut self.0, Scalar::ZERO) };
+    }
+}
+
 //----------------------------------   RistrettoSecretKey Debug --------------------------------------------//
 impl fmt::Debug for RistrettoSecretKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -56,20 +65,39 @@ impl RistrettoSecretKey {
         Ok(RistrettoSecretKey(k))
     }
 
+    /// Return the secret key for 64 uniformly distributed bytes, such as the output of a 512-bit hash
+    pub fn from_uniform_bytes(bytes: &[u8]) -> Result<RistrettoSecretKey, Error> {
+        if bytes.len() != 64 {
+            return Err(Error::IncorrectByteLength);
+        }
+        let mut a = [0u8; 64];
+        a.copy_from_slice(bytes);
+        Ok(RistrettoSecretKey(Scalar::from_bytes_mod_order_wide(&a)))
+    }
+
     /// Return the byte array for the secret key in little-endian order
     pub fn as_bytes(&self) -> &[u8] {
         self.0.as_bytes()
     }
 }
 
+//----------------------------------   RistrettoSecretKey Eq ---------------------------------------------------------//
+
+impl PartialEq for RistrettoSecretKey {
+    fn eq(&self, other: &RistrettoSecretKey) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for RistrettoSecretKey {}
+
 //----------------------------------   RistrettoSecretKey Mul / Add / Sub --------------------------------------------//
 
 impl<'a, 'b> Mul<&'b RistrettoPublicKey> for &'a RistrettoSecretKey {
     type Output = RistrettoPublicKey;
 
     fn mul(self, rhs: &'b RistrettoPublicKey) -> RistrettoPublicKey {
-        let p = self.0 * rhs.point;
-        RistrettoPublicKey::new_from_pk(p)
+        mul(&self.0, rhs)
     }
 }
 
@@ -131,9 +159,12 @@ impl<'a> Borrow<Scalar> for &'a RistrettoSecretKey {
 
 //--------------------------------------------- Ristretto Public Key -------------------------------------------------//
 
+// At least one of the point and its encoding is always set, and the other is computed when first needed. The hardware
+// accelerated scalar multiplication produces encodings, which are often only sent to the host, so decompressing them
+// up front would be wasted work.
 #[derive(Clone)]
 pub struct RistrettoPublicKey {
-    point: RistrettoPoint,
+    point: OnceCell<RistrettoPoint>,
     compressed: OnceCell<CompressedRistretto>,
 }
 
@@ -141,31 +172,44 @@ impl RistrettoPublicKey {
     // Private constructor
     pub(super) fn new_from_pk(pk: RistrettoPoint) -> Self {
         Self {
-            point: pk,
+            point: pk.into(),
             compressed: OnceCell::new(),
         }
     }
 
+    // Private constructor for an encoding that is known to be valid, because it was computed rather than received
+    #[cfg(feature = "cx-ecc")]
+    fn new_from_valid_compressed(compressed: CompressedRistretto) -> Self {
+        Self {
+            point: OnceCell::new(),
+            compressed: compressed.into(),
+        }
+    }
+
     pub fn new_from_compressed(compressed: CompressedRistretto) -> Option<Self> {
         compressed.decompress().map(|point| Self {
             compressed: compressed.into(),
-            point,
+            point: point.into(),
         })
     }
 
     /// Return the embedded RistrettoPoint representation
     pub fn point(&self) -> RistrettoPoint {
-        self.point
+        *self.point.get_or_init(|| {
+            self.compressed
+                .get()
+                .and_then(|compressed| compressed.decompress())
+                .expect("a public key holds its point or a valid encoding")
+        })
     }
 
     pub(super) fn compressed(&self) -> &CompressedRistretto {
-        self.compressed.get_or_init(|| self.point.compress())
+        self.compressed.get_or_init(|| self.point().compress())
     }
 
     /// Generates a new Public key from the given secret key
     pub fn from_secret_key(k: &RistrettoSecretKey) -> RistrettoPublicKey {
-        let pk = &k.0 * RISTRETTO_BASEPOINT_TABLE;
-        RistrettoPublicKey::new_from_pk(pk)
+        mul_base_and_add(&k.0, &[])
     }
 
     // pub fn batch_mul(scalars: &[RistrettoSecretKey], points: &[Self]) -> Self {
@@ -215,13 +259,35 @@ impl Default for RistrettoPublicKey {
     }
 }
 
+//----------------------------------    Ristretto Public Key Eq / Ord  -----------------------------------------------//
+
+impl PartialEq for RistrettoPublicKey {
+    fn eq(&self, other: &RistrettoPublicKey) -> bool {
+        self.point() == other.point()
+    }
+}
+
+impl Eq for RistrettoPublicKey {}
+
+impl PartialOrd for RistrettoPublicKey {
+    fn partial_cmp(&self, other: &RistrettoPublicKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RistrettoPublicKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
 //----------------------------------         PublicKey Add / Sub / Mul   ---------------------------------------------//
 
 impl<'a, 'b> Add<&'b RistrettoPublicKey> for &'a RistrettoPublicKey {
     type Output = RistrettoPublicKey;
 
     fn add(self, rhs: &'b RistrettoPublicKey) -> RistrettoPublicKey {
-        let p_sum = self.point + rhs.point;
+        let p_sum = self.point() + rhs.point();
         RistrettoPublicKey::new_from_pk(p_sum)
     }
 }
@@ -230,7 +296,7 @@ impl<'a, 'b> Sub<&'b RistrettoPublicKey> for &'a RistrettoPublicKey {
     type Output = RistrettoPublicKey;
 
     fn sub(self, rhs: &RistrettoPublicKey) -> RistrettoPublicKey {
-        let p_sum = self.point - rhs.point;
+        let p_sum = self.point() - rhs.point();
         RistrettoPublicKey::new_from_pk(p_sum)
     }
 }
@@ -239,8 +305,7 @@ impl<'a, 'b> Mul<&'b RistrettoSecretKey> for &'a RistrettoPublicKey {
     type Output = RistrettoPublicKey;
 
     fn mul(self, rhs: &'b RistrettoSecretKey) -> RistrettoPublicKey {
-        let p = rhs.0 * self.point;
-        RistrettoPublicKey::new_from_pk(p)
+        mul(&rhs.0, self)
     }
 }
 
@@ -284,13 +349,13 @@ impl From<RistrettoSecretKey> for Scalar {
 
 impl From<RistrettoPublicKey> for RistrettoPoint {
     fn from(pk: RistrettoPublicKey) -> Self {
-        pk.point
+        pk.point()
     }
 }
 
 impl From<&RistrettoPublicKey> for RistrettoPoint {
     fn from(pk: &RistrettoPublicKey) -> Self {
-        pk.point
+        pk.point()
     }
 }
 
@@ -299,3 +364,103 @@ impl From<RistrettoPublicKey> for CompressedRistretto {
         *pk.compressed()
     }
 }
+
+//----------------------------------         Scalar multiplication      ----------------------------------------------//
+
+/// Compute `k.G + k_1.P_1 + k_2.P_2 + ...` for the Ristretto base point `G` and the constant points `P_i`, given by
+/// their encodings. With the `cx-ecc` feature this is done by the hardware accelerated ed25519 syscalls, falling back
+/// to software if a syscall fails.
+pub(crate) fn mul_base_and_add(k: &Scalar, terms: &[(&Scalar, &CompressedRistretto)]) -> RistrettoPublicKey {
+    #[cfg(feature = "cx-ecc")]
+    if let Some(compressed) = crate::cx_ristretto::mul_base_and_add(k, terms) {
+        return RistrettoPublicKey::new_from_valid_compressed(compressed);
+    }
+    let mut p = k * RISTRETTO_BASEPOINT_TABLE;
+    for (k_i, p_i) in terms {
+        p += *k_i * p_i.decompress().expect("constant points are valid Ristretto points");
+    }
+    RistrettoPublicKey::new_from_pk(p)
+}
+
+/// Multiply the Ristretto point `p` by `k`. With the `cx-ecc` feature this is done by the hardware accelerated ed25519
+/// syscalls on the encoding of `p`, falling back to software if a syscall fails.
+pub(crate) fn mul(k: &Scalar, p: &RistrettoPublicKey) -> RistrettoPublicKey {
+    #[cfg(feature = "cx-ecc")]
+    if let Some(compressed) = crate::cx_ristretto::mul(k, p.compressed()) {
+        return RistrettoPublicKey::new_from_valid_compressed(compressed);
+    }
+    RistrettoPublicKey::new_from_pk(k * p.point())
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use tari_crypto::{
+        keys::PublicKey,
+        ristretto::{RistrettoPublicKey as TariPublicKey, RistrettoSecretKey as TariSecretKey},
+        tari_utilities::ByteArray,
+    };
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    // Key bytes, including ones that are not canonical scalars and must be reduced
+    const KEYS: [[u8; 32]; 4] = [[0; 32], [1; 32], [0x42; 32], [0xff; 32]];
+
+    fn secret_keys(bytes: &[u8; 32]) -> (RistrettoSecretKey, TariSecretKey) {
+        (
+            RistrettoSecretKey::from_bytes(bytes).unwrap(),
+            TariSecretKey::from_bytes(bytes).unwrap(),
+        )
+    }
+
+    #[test]
+    fn secret_keys_match_tari_crypto() {
+        for bytes in KEYS {
+            let (k, tari_k) = secret_keys(&bytes);
+            assert_eq!(k.as_bytes(), tari_k.as_bytes());
+        }
+        assert_eq!(RistrettoSecretKey::from_bytes(&[1; 31]).is_err(), true);
+        assert_eq!(TariSecretKey::from_bytes(&[1; 31]).is_err(), true);
+    }
+
+    #[test]
+    fn public_keys_match_tari_crypto() {
+        for bytes in KEYS {
+            let (k, tari_k) = secret_keys(&bytes);
+            let p = RistrettoPublicKey::from_secret_key(&k);
+            let tari_p = TariPublicKey::from_secret_key(&tari_k);
+            assert_eq!(p.as_bytes(), tari_p.as_bytes());
+            assert_eq!(RistrettoPublicKey::from_bytes(tari_p.as_bytes()).unwrap() == p, true);
+        }
+    }
+
+    #[test]
+    fn invalid_public_keys_match_tari_crypto() {
+        // A non-canonical encoding, a point that is not on the curve and a wrong length
+        let invalid: [&[u8]; 3] = [&[0xff; 32], &[0x42; 32], &[0x01; 33]];
+        for bytes in invalid {
+            assert_eq!(RistrettoPublicKey::from_bytes(bytes).is_err(), true);
+            assert_eq!(TariPublicKey::from_bytes(bytes).is_err(), true);
+        }
+    }
+
+    #[test]
+    fn arithmetic_matches_tari_crypto() {
+        for pair in KEYS.windows(2) {
+            let (a, tari_a) = secret_keys(&pair[0]);
+            let (b, tari_b) = secret_keys(&pair[1]);
+            let p = RistrettoPublicKey::from_secret_key(&b);
+            let tari_p = TariPublicKey::from_secret_key(&tari_b);
+
+            assert_eq!((&a + &b).as_bytes(), (&tari_a + &tari_b).as_bytes());
+            assert_eq!((&a - &b).as_bytes(), (&tari_a - &tari_b).as_bytes());
+            assert_eq!((&a * &b).as_bytes(), (&tari_a * &tari_b).as_bytes());
+            assert_eq!((&p * &a).as_bytes(), (&tari_p * &tari_a).as_bytes());
+            let q = RistrettoPublicKey::from_secret_key(&a);
+            let tari_q = TariPublicKey::from_secret_key(&tari_a);
+            assert_eq!((&p + &q).as_bytes(), (&tari_p + &tari_q).as_bytes());
+            assert_eq!((&p - &q).as_bytes(), (&tari_p - &tari_q).as_bytes());
+        }
+    }
+}
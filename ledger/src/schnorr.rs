@@ -5,17 +5,23 @@
 //! This module defines generic traits for handling the digital signature operations, agnostic
 //! of the underlying elliptic curve implementation
 
-use core::ops::Add;
+use core::{cmp::Ordering, ops::Add};
 
 use digest::Digest;
-use digest::Update;
 
 use crate::{
     blake2::Blake256,
     errors::*,
+    hashing::{DomainSeparatedHash, DomainSeparatedHasher},
     ristretto_keys::{RistrettoPublicKey, RistrettoSecretKey},
 };
 
+// Define the hashing domain for Schnorr signatures
+hash_domain!(SchnorrSigChallenge, "com.tari.schnorr_signature", 1);
+
+/// The length of a byte encoded signature: the public nonce followed by the signature scalar
+pub const SIGNATURE_LENGTH: usize = 64;
+
 /// # SchnorrSignature
 ///
 /// Provides a Schnorr signature that is agnostic to a specific public/private key implementation.
@@ -86,12 +92,59 @@ impl SchnorrSignature {
     ) -> Result<Self, Error> {
         let public_nonce = RistrettoPublicKey::from_secret_key(&nonce);
         let public_key = RistrettoPublicKey::from_secret_key(secret);
-        let e = Blake256::default()
-            .chain(&public_nonce.as_bytes())
-            .chain(&public_key.as_bytes())
-            .chain(&message)
-            .finalize();
-        Self::sign_raw(secret, nonce, &e)
+        let e = Self::construct_domain_separated_challenge::<_, Blake256>(&public_nonce, &public_key, message);
+        Self::sign_raw(secret, nonce, e.as_ref())
+    }
+
+    /// Constructs an opinionated challenge hash for the given public nonce, public key and message.
+    ///
+    /// In general, the signature challenge is given by `H(R, P, m)`. Often, plain concatenation is used to construct
+    /// the challenge. In this implementation, the challenge is constructed by means of domain separated hashing using
+    /// the provided digest.
+    ///
+    /// This challenge is used in the [`sign_message`] and [`verify_message`] methods. If you wish to use a custom
+    /// challenge, you can use [`sign_raw`] instead.
+    pub fn construct_domain_separated_challenge<B, D>(
+        public_nonce: &RistrettoPublicKey,
+        public_key: &RistrettoPublicKey,
+        message: B,
+    ) -> DomainSeparatedHash<D>
+    where
+        B: AsRef<[u8]>,
+        D: Digest,
+    {
+        DomainSeparatedHasher::<D, SchnorrSigChallenge>::new_with_label("challenge")
+            .chain(public_nonce.as_bytes())
+            .chain(public_key.as_bytes())
+            .chain(message.as_ref())
+            .finalize()
+    }
+
+    /// Verifies a signature created by the `sign_message` method. The function returns `true` if and only if the
+    /// message was signed by the secret key corresponding to the given public key, and that the challenge was
+    /// constructed using the domain-separation method defined in [`construct_domain_separated_challenge`].
+    pub fn verify_message<B: AsRef<[u8]>>(&self, public_key: &RistrettoPublicKey, message: B) -> bool {
+        let challenge =
+            Self::construct_domain_separated_challenge::<_, Blake256>(&self.public_nonce, public_key, message);
+        self.verify_challenge(public_key, challenge.as_ref())
+    }
+
+    /// Returns true if this signature is valid for a public key and challenge, otherwise false. This will always return
+    /// false if the challenge is not exactly 32 bytes long.
+    pub fn verify_challenge(&self, public_key: &RistrettoPublicKey, challenge: &[u8]) -> bool {
+        let e = match RistrettoSecretKey::from_bytes(challenge) {
+            Ok(e) => e,
+            Err(_) => return false,
+        };
+        self.verify(public_key, &e)
+    }
+
+    /// Returns true if this signature is valid for a public key and challenge scalar, otherwise false.
+    pub fn verify(&self, public_key: &RistrettoPublicKey, challenge: &RistrettoSecretKey) -> bool {
+        // s.G = R + e.P
+        let lhs = RistrettoPublicKey::from_secret_key(&self.signature);
+        let rhs = &self.public_nonce + challenge * public_key;
+        lhs == rhs
     }
 
     /// Returns a reference to the `s` signature component.
@@ -103,6 +156,25 @@ impl SchnorrSignature {
     pub fn get_public_nonce(&self) -> &RistrettoPublicKey {
         &self.public_nonce
     }
+
+    /// Create a `SchnorrSignature` from its byte encoding, the public nonce followed by the signature scalar. Returns
+    /// an error if the slice is not exactly 64 bytes long or if the public nonce is not a valid point.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != SIGNATURE_LENGTH {
+            return Err(Error::IncorrectByteLength);
+        }
+        let public_nonce = RistrettoPublicKey::from_bytes(&bytes[..32])?;
+        let signature = RistrettoSecretKey::from_bytes(&bytes[32..])?;
+        Ok(Self::new(public_nonce, signature))
+    }
+
+    /// Return the byte encoding of the signature, the public nonce followed by the signature scalar.
+    pub fn to_bytes(&self) -> [u8; SIGNATURE_LENGTH] {
+        let mut bytes = [0u8; SIGNATURE_LENGTH];
+        bytes[..32].copy_from_slice(self.public_nonce.as_bytes());
+        bytes[32..].copy_from_slice(self.signature.as_bytes());
+        bytes
+    }
 }
 
 impl<'a, 'b> Add<&'b SchnorrSignature> for &'a SchnorrSignature {
@@ -130,3 +202,139 @@ impl Default for SchnorrSignature {
         SchnorrSignature::new(RistrettoPublicKey::default(), RistrettoSecretKey::default())
     }
 }
+
+impl PartialEq for SchnorrSignature {
+    fn eq(&self, other: &Self) -> bool {
+        self.public_nonce == other.public_nonce && self.signature == other.signature
+    }
+}
+
+impl Eq for SchnorrSignature {}
+
+/// Provide an efficient ordering algorithm for Schnorr signatures. It's probably not a good idea to implement `Ord`
+/// for secret keys, but in this instance, the signature is publicly known and is simply a scalar, so we use the byte
+/// representation of the scalar as the canonical ordering metric. This conversion is done if and only if the public
+/// nonces are already equal, otherwise the public nonce ordering determines the `SchnorrSignature` order.
+impl Ord for SchnorrSignature {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.public_nonce.cmp(&other.public_nonce) {
+            Ordering::Equal => self.signature.as_bytes().cmp(other.signature.as_bytes()),
+            v => v,
+        }
+    }
+}
+
+impl PartialOrd for SchnorrSignature {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use tari_crypto::{
+        keys::PublicKey,
+        ristretto::{RistrettoPublicKey as TariPublicKey, RistrettoSchnorr, RistrettoSecretKey as TariSecretKey},
+        tari_utilities::ByteArray,
+    };
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    // Pairs of key and nonce bytes, including ones that are not canonical scalars and must be reduced
+    const VECTORS: [([u8; 32], [u8; 32]); 3] = [([1; 32], [2; 32]), ([0x42; 32], [0x17; 32]), ([0xff; 32], [0x80; 32])];
+
+    // The same secret key in both implementations
+    fn secret_keys(bytes: &[u8; 32]) -> (RistrettoSecretKey, TariSecretKey) {
+        (
+            RistrettoSecretKey::from_bytes(bytes).unwrap(),
+            TariSecretKey::from_bytes(bytes).unwrap(),
+        )
+    }
+
+    #[test]
+    fn sign_raw_matches_tari_crypto() {
+        for (key_bytes, nonce_bytes) in VECTORS {
+            let (key, tari_key) = secret_keys(&key_bytes);
+            let (nonce, tari_nonce) = secret_keys(&nonce_bytes);
+            let challenge = Blake256::digest(&key_bytes);
+
+            let signature = SchnorrSignature::sign_raw(&key, nonce, &challenge).unwrap();
+            let expected = RistrettoSchnorr::sign_raw(&tari_key, tari_nonce, &challenge).unwrap();
+            assert_eq!(
+                signature.get_public_nonce().as_bytes(),
+                expected.get_public_nonce().as_bytes()
+            );
+            assert_eq!(
+                signature.get_signature().as_bytes(),
+                expected.get_signature().as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn sign_message_matches_tari_crypto() {
+        for (key_bytes, nonce_bytes) in VECTORS {
+            let (key, tari_key) = secret_keys(&key_bytes);
+            let (nonce, tari_nonce) = secret_keys(&nonce_bytes);
+            let message = b"Tari Ledger Demo";
+
+            let signature = SchnorrSignature::sign_with_nonce_and_message(&key, nonce, message).unwrap();
+            let expected = RistrettoSchnorr::sign_with_nonce_and_message(&tari_key, tari_nonce, message).unwrap();
+            assert_eq!(
+                signature.get_public_nonce().as_bytes(),
+                expected.get_public_nonce().as_bytes()
+            );
+            assert_eq!(
+                signature.get_signature().as_bytes(),
+                expected.get_signature().as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn verifies_tari_crypto_signatures() {
+        for (key_bytes, nonce_bytes) in VECTORS {
+            let (key, tari_key) = secret_keys(&key_bytes);
+            let (_, tari_nonce) = secret_keys(&nonce_bytes);
+            let public_key = RistrettoPublicKey::from_secret_key(&key);
+            assert_eq!(
+                public_key.as_bytes(),
+                TariPublicKey::from_secret_key(&tari_key).as_bytes()
+            );
+
+            let expected = RistrettoSchnorr::sign_with_nonce_and_message(&tari_key, tari_nonce, b"message").unwrap();
+            let mut bytes = [0u8; SIGNATURE_LENGTH];
+            bytes[..32].copy_from_slice(expected.get_public_nonce().as_bytes());
+            bytes[32..].copy_from_slice(expected.get_signature().as_bytes());
+            let signature = SchnorrSignature::from_bytes(&bytes).unwrap();
+            assert_eq!(signature.to_bytes(), bytes);
+            assert_eq!(signature.verify_message(&public_key, b"message"), true);
+            assert_eq!(signature.verify_message(&public_key, b"other message"), false);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_signatures() {
+        let (key, _) = secret_keys(&VECTORS[0].0);
+        let (nonce, _) = secret_keys(&VECTORS[0].1);
+        let (other_key, _) = secret_keys(&VECTORS[1].0);
+        let public_key = RistrettoPublicKey::from_secret_key(&key);
+        let challenge = Blake256::digest(b"challenge");
+        let signature = SchnorrSignature::sign_raw(&key, nonce, &challenge).unwrap();
+
+        assert_eq!(signature.verify_challenge(&public_key, &challenge), true);
+        assert_eq!(
+            signature.verify_challenge(&RistrettoPublicKey::from_secret_key(&other_key), &challenge),
+            false
+        );
+        assert_eq!(signature.verify_challenge(&public_key, &challenge[..31]), false);
+        let tampered = SchnorrSignature::new(signature.get_public_nonce().clone(), signature.get_signature() + &key);
+        assert_eq!(tampered.verify_challenge(&public_key, &challenge), false);
+        assert_eq!(
+            SchnorrSignature::from_bytes(&[0u8; SIGNATURE_LENGTH - 1]).is_err(),
+            true
+        );
+    }
+}
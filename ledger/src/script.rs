@@ -0,0 +1,253 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! TariScript decoding
+//! Every output carries a TariScript that decides who can spend it. The script is a sequence of opcodes, each a
+//! single byte followed by its fixed length operand, as serialized by `tari_script`. Executing the script must leave
+//! a single public key on the stack, which the spender proves knowledge of with the script signature.
+//! - Only the opcodes needed to review common scripts are decoded. The operand length of any other opcode is unknown,
+//!   so a script containing one cannot be reviewed at all and decoding stops at that opcode.
+//! - The depth of the stack is tracked along with whether its top item is a pushed key, so that a script only counts as
+//!   paying to a key if it leaves that key, and nothing else, on the stack.
+
+use crate::{errors::Error, ristretto_keys::RistrettoPublicKey};
+
+const OP_CHECK_HEIGHT_VERIFY: u8 = 0x66;
+const OP_CHECK_HEIGHT: u8 = 0x67;
+const OP_COMPARE_HEIGHT_VERIFY: u8 = 0x68;
+const OP_COMPARE_HEIGHT: u8 = 0x69;
+pub const OP_DROP: u8 = 0x70;
+const OP_DUP: u8 = 0x71;
+const OP_REV_ROT: u8 = 0x72;
+const OP_NOP: u8 = 0x73;
+const OP_PUSH_HASH: u8 = 0x7a;
+const OP_PUSH_ZERO: u8 = 0x7b;
+const OP_PUSH_ONE: u8 = 0x7c;
+const OP_PUSH_INT: u8 = 0x7d;
+pub const OP_PUSH_PUBKEY: u8 = 0x7e;
+
+/// What a script does, as far as the device can tell
+#[derive(Clone, PartialEq, Eq)]
+pub enum ScriptKind {
+    /// `Nop`, so the output is spent with the key supplied in the input data
+    Standard,
+    /// The script leaves only this key on the stack, which is the key that spends the output
+    PayToKey(RistrettoPublicKey),
+    /// The script only uses decoded opcodes, but does not match a known pattern, or would fail
+    Custom,
+    /// The script uses an opcode the device cannot decode
+    Unknown,
+}
+
+/// A summary of a TariScript for the review screens
+#[derive(Clone, PartialEq, Eq)]
+pub struct ScriptSummary {
+    pub kind: ScriptKind,
+    /// The height before which the output cannot be spent, if the script checks one
+    pub lock_height: Option<u64>,
+}
+
+impl ScriptSummary {
+    /// Decode and validate the script `bytes`
+    /// - Returns `Error::InvalidScript` if the script is empty or an operand is cut short.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() {
+            return Err(Error::InvalidScript);
+        }
+
+        let mut lock_height = None;
+        // The number of items on the stack, or `None` once an opcode would pop more items than there are
+        let mut depth = Some(0usize);
+        // The key on top of the stack, if the top item is a pushed key
+        let mut top_key = None;
+        let mut only_nops = true;
+        let mut position = 0;
+        while position < bytes.len() {
+            let opcode = bytes[position];
+            let operand = match operand_length(opcode) {
+                Some(length) => bytes
+                    .get(position + 1..position + 1 + length)
+                    .ok_or(Error::InvalidScript)?,
+                None => {
+                    return Ok(Self {
+                        kind: ScriptKind::Unknown,
+                        lock_height,
+                    })
+                },
+            };
+            position += 1 + operand.len();
+
+            only_nops &= opcode == OP_NOP;
+            if opcode == OP_CHECK_HEIGHT_VERIFY {
+                let height = read_u64(operand);
+                lock_height = Some(lock_height.map_or(height, |current: u64| current.max(height)));
+            }
+            let (pops, pushes) = stack_effect(opcode);
+            depth = depth
+                .and_then(|depth| depth.checked_sub(pops))
+                .map(|depth| depth + pushes);
+            top_key = match opcode {
+                OP_PUSH_PUBKEY => Some(RistrettoPublicKey::from_bytes(operand).map_err(|_| Error::InvalidScript)?),
+                // A duplicated key is still on top, and checking a height leaves the stack as it is
+                OP_DUP | OP_NOP | OP_CHECK_HEIGHT_VERIFY => top_key,
+                _ => None,
+            };
+        }
+
+        let kind = match (depth, top_key) {
+            (Some(1), Some(public_key)) => ScriptKind::PayToKey(public_key),
+            _ if only_nops => ScriptKind::Standard,
+            _ => ScriptKind::Custom,
+        };
+        Ok(Self { kind, lock_height })
+    }
+
+    /// Whether the script cannot be reviewed, so that approving it amounts to blind signing
+    pub fn is_blind(&self) -> bool {
+        self.kind == ScriptKind::Unknown
+    }
+}
+
+// The operand length of a decoded opcode, or `None` for an opcode the device does not decode
+fn operand_length(opcode: u8) -> Option<usize> {
+    match opcode {
+        OP_CHECK_HEIGHT_VERIFY | OP_CHECK_HEIGHT | OP_PUSH_INT => Some(8),
+        OP_PUSH_HASH | OP_PUSH_PUBKEY => Some(32),
+        OP_COMPARE_HEIGHT_VERIFY |
+        OP_COMPARE_HEIGHT |
+        OP_DROP |
+        OP_DUP |
+        OP_REV_ROT |
+        OP_NOP |
+        OP_PUSH_ZERO |
+        OP_PUSH_ONE => Some(0),
+        _ => None,
+    }
+}
+
+// The number of items a decoded opcode pops off the stack and pushes onto it, as executed by `tari_script`
+fn stack_effect(opcode: u8) -> (usize, usize) {
+    match opcode {
+        OP_CHECK_HEIGHT | OP_PUSH_HASH | OP_PUSH_ZERO | OP_PUSH_ONE | OP_PUSH_INT | OP_PUSH_PUBKEY => (0, 1),
+        OP_COMPARE_HEIGHT_VERIFY | OP_DROP => (1, 0),
+        OP_COMPARE_HEIGHT => (1, 1),
+        OP_DUP => (1, 2),
+        OP_REV_ROT => (3, 3),
+        _ => (0, 0),
+    }
+}
+
+// Read a little-endian u64 from an 8 byte operand
+fn read_u64(operand: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(operand);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+    use crate::ristretto_keys::RistrettoSecretKey;
+
+    fn public_key(byte: u8) -> RistrettoPublicKey {
+        RistrettoPublicKey::from_secret_key(&RistrettoSecretKey::from_bytes(&[byte; 32]).unwrap())
+    }
+
+    // `PushPubKey(key)`
+    fn push_key(script: &mut [u8], key: &RistrettoPublicKey) -> usize {
+        script[0] = OP_PUSH_PUBKEY;
+        script[1..33].copy_from_slice(key.as_bytes());
+        33
+    }
+
+    fn kind(script: &[u8]) -> ScriptKind {
+        ScriptSummary::from_bytes(script).unwrap().kind
+    }
+
+    #[test]
+    fn pay_to_key() {
+        let key = public_key(1);
+        let mut script = [0u8; 80];
+        let length = push_key(&mut script, &key);
+        assert_eq!(kind(&script[..length]) == ScriptKind::PayToKey(key.clone()), true);
+
+        // A one-sided payment drops the sender's public nonce before pushing the script key
+        let length = push_key(&mut script, &public_key(2));
+        script[length] = OP_DROP;
+        let length = length + 1 + push_key(&mut script[length + 1..], &key);
+        assert_eq!(kind(&script[..length]) == ScriptKind::PayToKey(key.clone()), true);
+
+        // And a time-locked one checks the height first
+        script[0] = OP_CHECK_HEIGHT_VERIFY;
+        script[1..9].copy_from_slice(&1000u64.to_le_bytes());
+        let length = 9 + push_key(&mut script[9..], &key);
+        let summary = ScriptSummary::from_bytes(&script[..length]).unwrap();
+        assert_eq!(summary.kind == ScriptKind::PayToKey(key), true);
+        assert_eq!(summary.lock_height == Some(1000), true);
+    }
+
+    #[test]
+    fn extra_stack_items_are_custom() {
+        let key = public_key(1);
+        let mut script = [0u8; 80];
+
+        // `PushPubKey(X) PushPubKey(K)` leaves two keys, so it does not pay to `K`
+        let length = push_key(&mut script, &public_key(2));
+        let length = length + push_key(&mut script[length..], &key);
+        assert_eq!(kind(&script[..length]) == ScriptKind::Custom, true);
+
+        // Nor does `PushOne PushPubKey(K)`
+        script[0] = OP_PUSH_ONE;
+        let length = 1 + push_key(&mut script[1..], &key);
+        assert_eq!(kind(&script[..length]) == ScriptKind::Custom, true);
+
+        // And `PushPubKey(K) Drop Drop` would fail, as it drops more items than there are
+        let length = push_key(&mut script, &key);
+        script[length..length + 2].copy_from_slice(&[OP_DROP, OP_DROP]);
+        assert_eq!(kind(&script[..length + 2]) == ScriptKind::Custom, true);
+
+        // While `PushPubKey(K) Drop` leaves nothing
+        assert_eq!(kind(&script[..length + 1]) == ScriptKind::Custom, true);
+    }
+
+    #[test]
+    fn nops_are_standard() {
+        assert_eq!(kind(&[OP_NOP]) == ScriptKind::Standard, true);
+        assert_eq!(kind(&[OP_NOP, OP_NOP]) == ScriptKind::Standard, true);
+        assert_eq!(kind(&[OP_NOP, OP_PUSH_ONE]) == ScriptKind::Custom, true);
+    }
+
+    #[test]
+    fn malformed_scripts_are_rejected() {
+        assert_eq!(ScriptSummary::from_bytes(&[]).is_err(), true);
+
+        // Operands cut short
+        let mut script = [0u8; 33];
+        push_key(&mut script, &public_key(1));
+        assert_eq!(ScriptSummary::from_bytes(&script[..32]).is_err(), true);
+        assert_eq!(ScriptSummary::from_bytes(&script[..1]).is_err(), true);
+        assert_eq!(
+            ScriptSummary::from_bytes(&[OP_CHECK_HEIGHT_VERIFY, 0, 0, 0]).is_err(),
+            true
+        );
+        assert_eq!(ScriptSummary::from_bytes(&[OP_PUSH_INT, 1]).is_err(), true);
+
+        // A pushed key that is not a valid encoding
+        script[1..33].copy_from_slice(&[0xff; 32]);
+        assert_eq!(ScriptSummary::from_bytes(&script).is_err(), true);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_blind() {
+        // `CheckSig`, which the device does not decode, stops decoding even before a malformed operand
+        for script in [&[0xac][..], &[OP_NOP, 0xac, OP_PUSH_PUBKEY], &[0x00]] {
+            let summary = ScriptSummary::from_bytes(script).unwrap();
+            assert_eq!(summary.kind == ScriptKind::Unknown, true);
+            assert_eq!(summary.is_blind(), true);
+        }
+        assert_eq!(ScriptSummary::from_bytes(&[OP_NOP]).unwrap().is_blind(), false);
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Persistent settings
+//! The user's preferences are kept in NVM, so that they survive the app being closed. They are stored as a fixed
+//! layout of `version (1) || blind signing (1) || expert mode (1) || network (1) || default account (8) || timeout
+//! (1) || high fee threshold (1)`, and a store with any other version, such as the zeroed NVM of a fresh install, reads
+//! as the defaults.
+
+use nanos_sdk::{
+    nvm::{AtomicStorage, SingleStorage},
+    NVMData,
+};
+
+use crate::{address::Network, timeout::TIMEOUT_MINUTES, transaction::HIGH_FEE_THRESHOLDS};
+
+// The version of the layout, to be bumped whenever it changes
+const SETTINGS_VERSION: u8 = 3;
+const SETTINGS_LENGTH: usize = 14;
+
+#[link_section = ".nvm_data"]
+static mut SETTINGS: NVMData<AtomicStorage<[u8; SETTINGS_LENGTH]>> =
+    NVMData::new(AtomicStorage::new(&[0u8; SETTINGS_LENGTH]));
+
+/// The user's preferences
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// Whether opaque challenges may be signed without review
+    pub blind_signing: bool,
+    /// Whether the review screens show technical details, such as the fee per gram
+    pub expert_mode: bool,
+    /// The network addresses are shown and accepted for
+    pub network: Network,
+    /// The account used by instructions that do not name one
+    pub default_account: u64,
+    /// The minutes of inactivity after which the session is wiped, one of `TIMEOUT_MINUTES`
+    pub timeout_minutes: u8,
+    /// The fee in micro Tari per gram above which a transaction gets a warning, one of `HIGH_FEE_THRESHOLDS`
+    pub high_fee_threshold: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            blind_signing: false,
+            expert_mode: false,
+            network: Network::Esmeralda,
+            default_account: 0,
+            timeout_minutes: 3,
+            high_fee_threshold: 50,
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings from NVM
+    pub fn load() -> Self {
+        let bytes = unsafe { SETTINGS.get_mut() }.get_ref();
+        Self::from_bytes(bytes).unwrap_or_default()
+    }
+
+    /// Write the settings to NVM
+    pub fn store(&self) {
+        unsafe { SETTINGS.get_mut() }.update(&self.to_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8; SETTINGS_LENGTH]) -> Option<Self> {
+        if bytes[0] != SETTINGS_VERSION {
+            return None;
+        }
+        let mut account_bytes = [0u8; 8];
+        account_bytes.copy_from_slice(&bytes[4..12]);
+        if !TIMEOUT_MINUTES.contains(&bytes[12]) || !HIGH_FEE_THRESHOLDS.contains(&bytes[13]) {
+            return None;
+        }
+        Some(Self {
+            blind_signing: bytes[1] == 1,
+            expert_mode: bytes[2] == 1,
+            network: Network::from_byte(bytes[3]).ok()?,
+            default_account: u64::from_le_bytes(account_bytes),
+            timeout_minutes: bytes[12],
+            high_fee_threshold: bytes[13],
+        })
+    }
+
+    fn to_bytes(self) -> [u8; SETTINGS_LENGTH] {
+        let mut bytes = [0u8; SETTINGS_LENGTH];
+        bytes[0] = SETTINGS_VERSION;
+        bytes[1] = u8::from(self.blind_signing);
+        bytes[2] = u8::from(self.expert_mode);
+        bytes[3] = self.network as u8;
+        bytes[4..12].copy_from_slice(&self.default_account.to_le_bytes());
+        bytes[12] = self.timeout_minutes;
+        bytes[13] = self.high_fee_threshold;
+        bytes
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Diffie-Hellman shared secrets
+//! The shared secret `k.P` of a device-held key `k` and a remote public key `P` never leaves the device as a point.
+//! Only its hash under one of a fixed set of labels is released, each the derivation a Tari protocol uses, so that a
+//! host can neither choose its own hash of the point nor reuse a secret meant for one protocol in another.
+
+use crate::{
+    errors::Error,
+    ristretto_keys::{RistrettoPublicKey, RistrettoSecretKey},
+    stealth::stealth_shared_secret,
+    DomainSeparatedConsensusHasher,
+    TransactionHashDomain,
+};
+
+/// The labels a shared secret can be hashed under
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SharedSecretLabel {
+    /// The key that encrypts the value and mask of an output exchanged with another party
+    DataEncryptionKey = 0,
+    /// The script key tweak `c` of a one-sided stealth output
+    StealthAddress = 1,
+    /// The commitment mask of a one-sided stealth output
+    CommitmentMask = 2,
+}
+
+impl SharedSecretLabel {
+    pub fn from_byte(byte: u8) -> Result<Self, Error> {
+        match byte {
+            0 => Ok(Self::DataEncryptionKey),
+            1 => Ok(Self::StealthAddress),
+            2 => Ok(Self::CommitmentMask),
+            _ => Err(Error::UnknownLabel),
+        }
+    }
+
+    /// The name of the label, as shown to the user
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::DataEncryptionKey => "data_encryption_key",
+            Self::StealthAddress => "stealth_address",
+            Self::CommitmentMask => "commitment_mask",
+        }
+    }
+}
+
+/// Compute the hash of the shared secret of `secret_key` and `public_key` under `label`
+pub fn shared_secret(
+    secret_key: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+    label: SharedSecretLabel,
+) -> Result<RistrettoSecretKey, Error> {
+    match label {
+        SharedSecretLabel::DataEncryptionKey | SharedSecretLabel::CommitmentMask => {
+            let hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new(label.as_str())
+                .chain(&(secret_key * public_key).as_bytes())
+                .finalize();
+            RistrettoSecretKey::from_bytes(&hash)
+        },
+        SharedSecretLabel::StealthAddress => stealth_shared_secret(secret_key, public_key),
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! One-sided stealth payments
+//! The sender of a one-sided payment to the public key `K` picks a nonce `r` and publishes `R = r.G` in the output's
+//! script. Both sides can compute the shared secret `c = H(r.K) = H(k.R)`, and the output is locked to the stealth
+//! script key `K_s = c.G + K`, which only the recipient can spend with `k + c`. Outputs to the same recipient are
+//! therefore unlinkable, while the recipient finds them by recomputing `K_s` for every `R`.
+//! - The recipient's view key `v` finds its outputs instead: the output's encrypted data, keyed by `H(r.V) = H(v.R)`,
+//!   must decrypt to a value and mask that open the output's commitment. A matching script key alone says nothing of
+//!   what the commitment holds, so it does not make an output the recipient's.
+//! - The addresses of the device are those of its view keys, so `K = V` and the device spends a one-sided payment it
+//!   received with the script key `v + c`, see `stealth_script_key`, and the commitment mask `H(v.R)` under its label.
+//! - The device picks `r` at random for every payment, so that no host can have two payments share `R` or a mask. For
+//!   `EncryptData` to derive the key `H(r.K)` of the output's encrypted data later, `r` is handed to the host sealed
+//!   with the sender's view key and bound to `K`, see `seal_nonce`, so that `r` itself never leaves the device.
+//! - The output's script `PushPubKey(R) Drop PushPubKey(K_s)` publishes `R` and pays to `K_s`, and its commitment mask
+//!   is the shared secret under its own label, so the recipient can open the output without hearing from the sender.
+
+use blake2::{digest::consts::U64, Blake2b};
+
+use crate::{
+    encrypted_data::{EncryptedData, ENCRYPTED_DATA_LENGTH},
+    errors::Error,
+    hashing::DomainSeparatedHasher,
+    pedersen::{PedersenCommitment, PedersenCommitmentFactory},
+    ristretto_keys::{RistrettoPublicKey, RistrettoSecretKey},
+    script::{OP_DROP, OP_PUSH_PUBKEY},
+    shared_secret::{shared_secret, SharedSecretLabel},
+};
+
+// Define the hashing domain for stealth addresses
+hash_domain!(StealthAddressDomain, "com.tari.base_layer.wallet.stealth_address", 1);
+
+/// The shared secret `c = H(private_key.public_key)` of a stealth output
+pub fn stealth_shared_secret(
+    private_key: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+) -> Result<RistrettoSecretKey, Error> {
+    let hash = DomainSeparatedHasher::<Blake2b<U64>, StealthAddressDomain>::new_with_label("stealth_address")
+        .chain((private_key * public_key).as_bytes())
+        .finalize();
+    RistrettoSecretKey::from_uniform_bytes(hash.as_ref())
+}
+
+/// The stealth script key `K_s = c.G + K` for the shared secret `c` and the recipient's public key `K`
+pub fn stealth_script_public_key(
+    shared_secret: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+) -> RistrettoPublicKey {
+    &RistrettoPublicKey::from_secret_key(shared_secret) + public_key
+}
+
+/// The stealth script key `v + c` of a one-sided payment with public nonce `public_nonce` to the view key `view_key`,
+/// the secret key of `K_s`
+pub fn stealth_script_key(
+    view_key: &RistrettoSecretKey,
+    public_nonce: &RistrettoPublicKey,
+) -> Result<RistrettoSecretKey, Error> {
+    Ok(&stealth_shared_secret(view_key, public_nonce)? + view_key)
+}
+
+/// The length of a sealed nonce, see `seal_nonce`
+pub const SEALED_NONCE_LENGTH: usize = ENCRYPTED_DATA_LENGTH;
+
+/// Seal the nonce `nonce` of a one-sided payment to `public_key` with the sender's view key `view_key`, for the host to
+/// hand back to `unseal_nonce`
+/// - The nonce is encrypted as the mask of encrypted data whose AEAD key is bound to `public_key` in place of a
+///   commitment, so that it only unseals for a payment to the same recipient.
+pub fn seal_nonce(
+    view_key: &RistrettoSecretKey,
+    nonce: &RistrettoSecretKey,
+    public_key: &RistrettoPublicKey,
+) -> Result<EncryptedData, Error> {
+    let recipient = PedersenCommitment::from_bytes(public_key.as_bytes())?;
+    EncryptedData::encrypt_data(&sealing_key(view_key)?, &recipient, 0, nonce)
+}
+
+/// Recover the nonce sealed by `seal_nonce` for a payment to `public_key`
+/// - Returns `Error::DecryptFailed` if it was sealed for another recipient or by another view key, or was altered.
+pub fn unseal_nonce(
+    view_key: &RistrettoSecretKey,
+    sealed_nonce: &EncryptedData,
+    public_key: &RistrettoPublicKey,
+) -> Result<RistrettoSecretKey, Error> {
+    let recipient = PedersenCommitment::from_bytes(public_key.as_bytes())?;
+    let (_, nonce) = sealed_nonce.decrypt_data(&sealing_key(view_key)?, &recipient)?;
+    Ok(nonce)
+}
+
+// The key that seals nonces, hashed from the view key so that it keys nothing else
+fn sealing_key(view_key: &RistrettoSecretKey) -> Result<RistrettoSecretKey, Error> {
+    let hash = DomainSeparatedHasher::<Blake2b<U64>, StealthAddressDomain>::new_with_label("sealed_nonce")
+        .chain(view_key.as_bytes())
+        .finalize();
+    RistrettoSecretKey::from_uniform_bytes(hash.as_ref())
+}
+
+/// The length of the script of a one-sided payment
+pub const STEALTH_SCRIPT_LENGTH: usize = 67;
+
+/// The script `PushPubKey(R) Drop PushPubKey(K_s)` of a one-sided payment with the public nonce `public_nonce` to the
+/// stealth script key `script_public_key`
+pub fn stealth_script(
+    public_nonce: &RistrettoPublicKey,
+    script_public_key: &RistrettoPublicKey,
+) -> [u8; STEALTH_SCRIPT_LENGTH] {
+    let mut script = [0u8; STEALTH_SCRIPT_LENGTH];
+    script[0] = OP_PUSH_PUBKEY;
+    script[1..33].copy_from_slice(public_nonce.as_bytes());
+    script[33] = OP_DROP;
+    script[34] = OP_PUSH_PUBKEY;
+    script[35..].copy_from_slice(script_public_key.as_bytes());
+    script
+}
+
+/// The length of a scanned output record: the sender's public nonce `R`, the output's commitment and its encrypted data
+pub const STEALTH_RECORD_LENGTH: usize = 64 + ENCRYPTED_DATA_LENGTH;
+
+/// The largest number of view keys an output scanner checks every output against
+pub const MAX_SCAN_ADDRESSES: usize = 4;
+
+/// Scans outputs for those sent to one of a few view keys, which stay on the device
+pub struct OutputScanner {
+    keys: [RistrettoSecretKey; MAX_SCAN_ADDRESSES],
+    count: usize,
+}
+
+impl OutputScanner {
+    /// Create a scanner for the view keys in `keys`, the first of which has index 0
+    pub fn new(keys: &[RistrettoSecretKey]) -> Result<Self, Error> {
+        if keys.is_empty() || keys.len() > MAX_SCAN_ADDRESSES {
+            return Err(Error::IncorrectByteLength);
+        }
+        let mut scanner = Self {
+            keys: Default::default(),
+            count: keys.len(),
+        };
+        for (slot, key) in scanner.keys.iter_mut().zip(keys) {
+            *slot = key.clone();
+        }
+        Ok(scanner)
+    }
+
+    /// Return the index of the view key the output with public nonce `public_nonce`, commitment `commitment` and
+    /// encrypted data `encrypted_data` was sent to, with the output's value, if any
+    pub fn scan(
+        &self,
+        public_nonce: &RistrettoPublicKey,
+        commitment: &PedersenCommitment,
+        encrypted_data: &EncryptedData,
+    ) -> Result<Option<(usize, u64)>, Error> {
+        for (index, view_key) in self.keys[..self.count].iter().enumerate() {
+            let encryption_key = shared_secret(view_key, public_nonce, SharedSecretLabel::DataEncryptionKey)?;
+            let (value, mask) = match encrypted_data.decrypt_data(&encryption_key, commitment) {
+                Ok(opening) => opening,
+                Err(Error::DecryptFailed) => continue,
+                Err(e) => return Err(e),
+            };
+            if PedersenCommitmentFactory::default().commit_value(&mask, value) == *commitment {
+                return Ok(Some((index, value)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    #[test]
+    fn recipient_spends_payments() {
+        let view_key = RistrettoSecretKey::from_bytes(&[1u8; 32]).unwrap();
+        let nonce = RistrettoSecretKey::from_bytes(&[2u8; 32]).unwrap();
+        let view_public_key = RistrettoPublicKey::from_secret_key(&view_key);
+        let public_nonce = RistrettoPublicKey::from_secret_key(&nonce);
+
+        // The sender's `K_s = c.G + V` is the public key of the recipient's `v + c`
+        let secret = stealth_shared_secret(&nonce, &view_public_key).unwrap();
+        let script_public_key = stealth_script_public_key(&secret, &view_public_key);
+        let script_key = stealth_script_key(&view_key, &public_nonce).unwrap();
+        assert_eq!(
+            RistrettoPublicKey::from_secret_key(&script_key).as_bytes(),
+            script_public_key.as_bytes()
+        );
+
+        // As is the mask of the output's commitment, which the scan opens
+        let mask = shared_secret(&nonce, &view_public_key, SharedSecretLabel::CommitmentMask).unwrap();
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, 100);
+        let encryption_key = shared_secret(&nonce, &view_public_key, SharedSecretLabel::DataEncryptionKey).unwrap();
+        let encrypted_data = EncryptedData::encrypt_data(&encryption_key, &commitment, 100, &mask).unwrap();
+        let scanner = OutputScanner::new(&[view_key.clone()]).unwrap();
+        let found = scanner.scan(&public_nonce, &commitment, &encrypted_data).unwrap();
+        assert_eq!(found == Some((0, 100)), true);
+        let spend_mask = shared_secret(&view_key, &public_nonce, SharedSecretLabel::CommitmentMask).unwrap();
+        assert_eq!(spend_mask.as_bytes(), mask.as_bytes());
+    }
+
+    #[test]
+    fn sealed_nonces_unseal_for_their_recipient() {
+        let view_key = RistrettoSecretKey::from_bytes(&[1u8; 32]).unwrap();
+        let nonce = RistrettoSecretKey::from_bytes(&[2u8; 32]).unwrap();
+        let recipient = RistrettoPublicKey::from_secret_key(&RistrettoSecretKey::from_bytes(&[3u8; 32]).unwrap());
+        let other = RistrettoPublicKey::from_secret_key(&RistrettoSecretKey::from_bytes(&[4u8; 32]).unwrap());
+        let other_view_key = RistrettoSecretKey::from_bytes(&[5u8; 32]).unwrap();
+
+        let sealed_nonce = seal_nonce(&view_key, &nonce, &recipient).unwrap();
+        let unsealed = unseal_nonce(&view_key, &sealed_nonce, &recipient).unwrap();
+        assert_eq!(unsealed.as_bytes(), nonce.as_bytes());
+        assert_eq!(unseal_nonce(&view_key, &sealed_nonce, &other).is_err(), true);
+        assert_eq!(unseal_nonce(&other_view_key, &sealed_nonce, &recipient).is_err(), true);
+    }
+}
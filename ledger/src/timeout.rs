@@ -0,0 +1,56 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Inactivity timeout
+//! Session state, such as a transaction being signed or the keys of an output scan, would otherwise stay in RAM for as
+//! long as the app is open. The event loop counts the ticker events that arrive while neither a button is pressed nor
+//! an APDU received, and wipes the session and returns to the home screen once the timeout set in the settings passes.
+//! - Approvals wait for the user with the same timeout, and one that times out is rejected.
+
+use crate::settings::Settings;
+
+/// The timeouts the user can choose from, in minutes
+pub const TIMEOUT_MINUTES: [u8; 4] = [1, 3, 5, 10];
+
+// The ticker fires every 100 ms
+const TICKS_PER_MINUTE: u32 = 600;
+
+/// The timeout that follows `minutes` in `TIMEOUT_MINUTES`, wrapping around at the end
+pub fn next_timeout_minutes(minutes: u8) -> u8 {
+    let index = TIMEOUT_MINUTES.iter().position(|m| *m == minutes).unwrap_or(0);
+    TIMEOUT_MINUTES[(index + 1) % TIMEOUT_MINUTES.len()]
+}
+
+/// Counts the ticks since the last activity
+pub struct InactivityTimer {
+    idle_ticks: u32,
+    timeout_ticks: u32,
+}
+
+impl InactivityTimer {
+    /// Start counting, with the timeout set in the settings
+    pub fn new() -> Self {
+        let mut timer = Self {
+            idle_ticks: 0,
+            timeout_ticks: 0,
+        };
+        timer.reset();
+        timer
+    }
+
+    /// Start counting again after activity, picking up any change to the timeout in the settings
+    pub fn reset(&mut self) {
+        self.idle_ticks = 0;
+        self.timeout_ticks = u32::from(Settings::load().timeout_minutes) * TICKS_PER_MINUTE;
+    }
+
+    /// Count a tick, returning whether the timeout passed with it
+    /// - This is true only once, so that an idle device is not wiped over and over.
+    pub fn tick(&mut self) -> bool {
+        if self.idle_ticks > self.timeout_ticks {
+            return false;
+        }
+        self.idle_ticks += 1;
+        self.idle_ticks > self.timeout_ticks
+    }
+}
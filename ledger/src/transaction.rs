@@ -0,0 +1,786 @@
+// Copyright 2023. The Tari Project
+// SPDX-License-Identifier: BSD-3-Clause
+
+//! Transaction balance tracking
+//! A transaction is streamed to the device one input or output at a time, each with its opening, i.e. its value and the
+//! derivation index of its mask. The device recomputes every commitment from its opening and keeps running sums, so
+//! that when the host asks for the kernel signature it can check that the transaction conserves value, i.e. that
+//! `Σin - Σout - fee.H` is the claimed excess, before signing with the excess secret.
+//! - One-sided payments to external recipients are streamed as an address and a value, and the device builds the
+//!   recipient's output itself: its commitment mask, stealth script and sender offset key are derived from the
+//!   payment's nonce and the address, so the output that is signed pays the address the user reviewed. Every payment is
+//!   shown to the user before the kernel is signed.
+//! - Change is an output the device can prove is the account's own: besides opening its commitment, its script must be
+//!   exactly `PushPubKey(K)` for a script key `K` the device derives, so that the account can spend it as an input with
+//!   that key. Verified change is hidden from the review, while every other output the host adds is shown with its
+//!   value and script, as the device cannot tell whom it pays.
+//! - The weight of the transaction is tallied with the consensus weights, so that the fee per gram can be shown and a
+//!   host that inflates the fee is caught on the review screens. Only the metadata bound to an output counts, and only
+//!   once.
+//! - Commitments may be extended with extra blinding generators. The excess is then tracked per generator, and the
+//!   kernel is signed with one signature scalar per generator of the transaction's highest extension degree.
+//! - The script offset `Σ input script keys - Σ output sender offset keys` is accumulated the same way from keys the
+//!   device holds, and is only released with the kernel signature. Every input is added with exactly one script key and
+//!   every output with exactly one sender offset key, so that the offset always sums keys of both kinds and is never a
+//!   single key the host could then use. Sender offset keys are fresh random keys, generated for each output and never
+//!   used again, so that offsets released for different transactions spending the same inputs cannot be combined to
+//!   solve for any of their keys.
+//! - The script, features and covenant of every output are bound to it: each is hashed into the output's metadata as it
+//!   is reviewed, and once the user approves the transaction the device attests that metadata with a Schnorr signature
+//!   by the output's sender offset key. Features and covenant need no review for a plain output, which is attested with
+//!   standard features and an empty covenant.
+//! - The inputs' scripts are attested the same way with their script keys, bound to the kernel excess, and like the
+//!   metadata attestations only once the user approved the transaction.
+//! - These attestations are device-local: their challenges are not those of the consensus `ComAndPubSignature` metadata
+//!   and script signatures, so they do not validate on chain. They let the host check that the outputs and inputs it
+//!   builds are the ones the user reviewed.
+
+use crate::{
+    address::TariAddress,
+    errors::Error,
+    pedersen::{ExtensionDegree, PedersenCommitment, PedersenCommitmentFactory, MAX_EXTENSION_DEGREE},
+    ristretto_keys::{RistrettoPublicKey, RistrettoSecretKey},
+    schnorr::SchnorrSignature,
+    script::{ScriptSummary, OP_PUSH_PUBKEY},
+    DomainSeparatedConsensusHasher,
+    TransactionHashDomain,
+};
+
+/// The largest number of external recipients a transaction can pay
+pub const MAX_RECIPIENTS: usize = 4;
+
+/// The largest number of inputs a transaction can have
+pub const MAX_INPUTS: usize = 8;
+
+/// The largest number of outputs with a sender offset key a transaction can have, payments included
+pub const MAX_OUTPUTS: usize = 8;
+
+/// The fees in micro Tari per gram of transaction weight the user can choose to be warned above
+pub const HIGH_FEE_THRESHOLDS: [u8; 5] = [10, 25, 50, 100, 250];
+
+// The consensus weights of `TransactionWeight::v1`
+const KERNEL_WEIGHT: u64 = 10;
+const INPUT_WEIGHT: u64 = 8;
+const OUTPUT_WEIGHT: u64 = 53;
+const METADATA_BYTES_PER_GRAM: u64 = 16;
+
+// The bytes every output counts for in the weight besides its script, features and covenant: its encrypted data and its
+// minimum value promise
+const OUTPUT_DATA_BYTES: u64 = 80 + 8;
+
+// The borsh encodings of standard output features, whose coinbase extra is a `Vec` with a u32 length, and of an empty
+// covenant, which `tari_core` writes with a varint length
+const STANDARD_OUTPUT_FEATURES: [u8; 16] = [0; 16];
+const EMPTY_COVENANT: [u8; 1] = [0];
+
+// `TransactionKernelVersion::V0`
+const KERNEL_VERSION: u8 = 0;
+// `KernelFeatures::empty()`
+const KERNEL_FEATURES: u8 = 0;
+// The borsh encoding of `None`, as plain transactions have no burn commitment
+const NO_BURN_COMMITMENT: u8 = 0;
+
+/// A payment to an external recipient
+#[derive(Clone)]
+pub struct Payment {
+    pub address: TariAddress,
+    pub value: u64,
+}
+
+// An input the device holds the script key of
+struct InputMetadata {
+    commitment: PedersenCommitment,
+    script_key: RistrettoSecretKey,
+}
+
+// An output the device holds the sender offset key of, with the hashes of the script, features and covenant reviewed
+// for it
+struct OutputMetadata {
+    commitment: PedersenCommitment,
+    value: u64,
+    sender_offset_key: RistrettoSecretKey,
+    /// Whether the device knows whom the output pays, as it is verified change or a payment it built
+    verified: bool,
+    script: Option<ScriptSummary>,
+    script_hash: Option<[u8; 32]>,
+    features_hash: Option<[u8; 32]>,
+    covenant_hash: Option<[u8; 32]>,
+    /// The borsh encoded size of the script, features and covenant bound so far
+    bound_bytes: u64,
+}
+
+impl OutputMetadata {
+    // The grams the output's metadata counts for in the weight, with standard features and an empty covenant unless
+    // others were bound, rounded up per output like `TransactionWeight::round_up_features_and_scripts_size`
+    fn metadata_weight(&self) -> u64 {
+        let mut bytes = self.bound_bytes + OUTPUT_DATA_BYTES;
+        if self.features_hash.is_none() {
+            bytes += STANDARD_OUTPUT_FEATURES.len() as u64;
+        }
+        if self.covenant_hash.is_none() {
+            bytes += EMPTY_COVENANT.len() as u64;
+        }
+        (bytes + METADATA_BYTES_PER_GRAM - 1) / METADATA_BYTES_PER_GRAM
+    }
+}
+
+/// A kernel signature with one signature scalar `s_j = r_j + e.x_j` per blinding generator `G_j` of the excess, for the
+/// public nonce `R = Σ r_j.G_j`. With the default extension degree this is an ordinary Schnorr signature.
+pub struct KernelSignature {
+    public_nonce: RistrettoPublicKey,
+    signatures: [RistrettoSecretKey; MAX_EXTENSION_DEGREE],
+    extension_degree: ExtensionDegree,
+}
+
+impl KernelSignature {
+    /// The public nonce `R`
+    pub fn public_nonce(&self) -> &RistrettoPublicKey {
+        &self.public_nonce
+    }
+
+    /// The signature scalars, one per blinding generator
+    pub fn signatures(&self) -> &[RistrettoSecretKey] {
+        &self.signatures[..self.extension_degree.size()]
+    }
+}
+
+/// The running state of a transaction being streamed to the device
+pub struct TransactionState {
+    account: u64,
+    fee: u64,
+    lock_height: u64,
+    input_value: u64,
+    /// The inputs in the order they were added
+    inputs: [Option<InputMetadata>; MAX_INPUTS],
+    /// The outputs in the order they were added. The last one is the output that metadata streamed next is bound to.
+    outputs: [Option<OutputMetadata>; MAX_OUTPUTS],
+    /// The kernel excess the user approved, after which the transaction can no longer change
+    approved_excess: Option<RistrettoPublicKey>,
+    output_value: u64,
+    payments: [Option<Payment>; MAX_RECIPIENTS],
+    /// The highest extension degree of the commitments so far
+    extension_degree: ExtensionDegree,
+    /// `Σ input masks - Σ output masks`, per blinding generator
+    excess: [RistrettoSecretKey; MAX_EXTENSION_DEGREE],
+    /// `Σ input commitments - Σ output commitments`
+    commitment_sum: RistrettoPublicKey,
+    /// `Σ input script keys - Σ output sender offset keys`
+    script_offset: RistrettoSecretKey,
+    /// `Σ input script public keys - Σ output sender offset public keys`, with those of the inputs as supplied by the
+    /// host
+    script_offset_public_key: RistrettoPublicKey,
+}
+
+impl TransactionState {
+    pub fn new(account: u64, fee: u64, lock_height: u64) -> Self {
+        Self {
+            account,
+            fee,
+            lock_height,
+            input_value: 0,
+            inputs: Default::default(),
+            outputs: Default::default(),
+            approved_excess: None,
+            output_value: 0,
+            payments: Default::default(),
+            extension_degree: ExtensionDegree::DefaultPedersen,
+            excess: Default::default(),
+            commitment_sum: RistrettoPublicKey::default(),
+            script_offset: RistrettoSecretKey::default(),
+            script_offset_public_key: RistrettoPublicKey::default(),
+        }
+    }
+
+    /// The account all masks of the transaction are derived from
+    pub fn account(&self) -> u64 {
+        self.account
+    }
+
+    /// The fee of the kernel
+    pub fn fee(&self) -> u64 {
+        self.fee
+    }
+
+    /// The lock height of the kernel
+    pub fn lock_height(&self) -> u64 {
+        self.lock_height
+    }
+
+    /// Add an input, which must open to `value` with `masks`, one per blinding generator, and whose script key must
+    /// match the host's `script_public_key`
+    pub fn add_input(
+        &mut self,
+        commitment: &PedersenCommitment,
+        value: u64,
+        masks: &[RistrettoSecretKey],
+        script_key: &RistrettoSecretKey,
+        script_public_key: &RistrettoPublicKey,
+    ) -> Result<(), Error> {
+        self.check_not_approved()?;
+        let position = self
+            .inputs
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::TooManyInputs)?;
+        verify_public_key(script_key, script_public_key)?;
+        self.verify_opening(commitment, value, masks)?;
+        self.input_value = self
+            .input_value
+            .checked_add(value)
+            .ok_or(Error::TransactionNotBalanced)?;
+        for (excess, mask) in self.excess.iter_mut().zip(masks) {
+            *excess = &*excess + mask;
+        }
+        self.commitment_sum = &self.commitment_sum + commitment.as_public_key();
+        self.script_offset = &self.script_offset + script_key;
+        self.script_offset_public_key = &self.script_offset_public_key + script_public_key;
+        self.inputs[position] = Some(InputMetadata {
+            commitment: commitment.clone(),
+            script_key: script_key.clone(),
+        });
+        Ok(())
+    }
+
+    /// Add an output, which must open to `value` with `masks`, one per blinding generator, returning the public key of
+    /// the fresh sender offset key generated for it
+    /// - The script of the previous output must have been added, see `add_script`.
+    pub fn add_output(
+        &mut self,
+        commitment: &PedersenCommitment,
+        value: u64,
+        masks: &[RistrettoSecretKey],
+    ) -> Result<RistrettoPublicKey, Error> {
+        self.check_not_approved()?;
+        self.check_output_metadata()?;
+        let position = self
+            .outputs
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::TooManyOutputs)?;
+        self.verify_opening(commitment, value, masks)?;
+        let sender_offset_key = RistrettoSecretKey::random();
+        let sender_offset_public_key = RistrettoPublicKey::from_secret_key(&sender_offset_key);
+        self.output_value = self
+            .output_value
+            .checked_add(value)
+            .ok_or(Error::TransactionNotBalanced)?;
+        for (excess, mask) in self.excess.iter_mut().zip(masks) {
+            *excess = &*excess - mask;
+        }
+        self.commitment_sum = &self.commitment_sum - commitment.as_public_key();
+        self.script_offset = &self.script_offset - &sender_offset_key;
+        self.script_offset_public_key = &self.script_offset_public_key - &sender_offset_public_key;
+        self.outputs[position] = Some(OutputMetadata {
+            commitment: commitment.clone(),
+            value,
+            sender_offset_key,
+            verified: false,
+            script: None,
+            script_hash: None,
+            features_hash: None,
+            covenant_hash: None,
+            bound_bytes: 0,
+        });
+        Ok(sender_offset_public_key)
+    }
+
+    /// Add a change output, which is added as an output with the script `script`, which must be exactly
+    /// `PushPubKey(script_public_key)`, returning the public key of its sender offset key
+    pub fn add_change(
+        &mut self,
+        commitment: &PedersenCommitment,
+        value: u64,
+        masks: &[RistrettoSecretKey],
+        script_public_key: &RistrettoPublicKey,
+        script: &[u8],
+    ) -> Result<RistrettoPublicKey, Error> {
+        match script.split_first() {
+            Some((&OP_PUSH_PUBKEY, key)) if key == script_public_key.as_bytes() => {},
+            _ => return Err(Error::ChangeVerificationFailed),
+        }
+        let sender_offset_public_key = self.add_output(commitment, value, masks)?;
+        self.add_script(script)?;
+        self.last_output()?.verified = true;
+        Ok(sender_offset_public_key)
+    }
+
+    /// Add the script `script` to the output added last, which must not have one yet, returning its summary
+    pub fn add_script(&mut self, script: &[u8]) -> Result<&ScriptSummary, Error> {
+        let summary = ScriptSummary::from_bytes(script)?;
+        let output = self.last_output()?;
+        bind_metadata(&mut output.script_hash, "output_script", script)?;
+        // A script is borsh encoded with its length as a varint
+        output.bound_bytes += varint_size(script.len() as u64) + script.len() as u64;
+        Ok(output.script.insert(summary))
+    }
+
+    /// Add the borsh encoded features `features` to the output added last, which must not have any yet
+    pub fn add_features(&mut self, features: &[u8]) -> Result<(), Error> {
+        let output = self.last_output()?;
+        bind_metadata(&mut output.features_hash, "output_features", features)?;
+        output.bound_bytes += features.len() as u64;
+        Ok(())
+    }
+
+    /// Add the borsh encoded covenant `covenant` to the output added last, which must not have one yet
+    pub fn add_covenant(&mut self, covenant: &[u8]) -> Result<(), Error> {
+        let output = self.last_output()?;
+        bind_metadata(&mut output.covenant_hash, "output_covenant", covenant)?;
+        output.bound_bytes += covenant.len() as u64;
+        Ok(())
+    }
+
+    /// Add a one-sided payment of `value` to `address`, as an output with the commitment mask `mask` and the stealth
+    /// script `script` that the device derived for the address, returning the output's commitment and the public key of
+    /// its sender offset key
+    pub fn add_payment(
+        &mut self,
+        address: TariAddress,
+        value: u64,
+        mask: &RistrettoSecretKey,
+        script: &[u8],
+    ) -> Result<(PedersenCommitment, RistrettoPublicKey), Error> {
+        let slot = self
+            .payments
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::TooManyRecipients)?;
+        let commitment = PedersenCommitmentFactory::default().commit_value(mask, value);
+        let sender_offset_public_key = self.add_output(&commitment, value, core::slice::from_ref(mask))?;
+        self.add_script(script)?;
+        self.last_output()?.verified = true;
+        self.payments[slot] = Some(Payment { address, value });
+        Ok((commitment, sender_offset_public_key))
+    }
+
+    /// The weight of the transaction in grams, from the consensus weights of its kernel, inputs and outputs and the
+    /// size of the metadata bound to its outputs
+    pub fn weight(&self) -> u64 {
+        KERNEL_WEIGHT +
+            self.inputs.iter().flatten().count() as u64 * INPUT_WEIGHT +
+            self.outputs.iter().flatten().count() as u64 * OUTPUT_WEIGHT +
+            self.outputs
+                .iter()
+                .flatten()
+                .map(OutputMetadata::metadata_weight)
+                .sum::<u64>()
+    }
+
+    /// The fee in micro Tari per gram of weight
+    pub fn fee_per_gram(&self) -> u64 {
+        self.fee / self.weight()
+    }
+
+    /// Whether the fee per gram is above `threshold`, one of `HIGH_FEE_THRESHOLDS`, so that the user should be warned
+    pub fn is_fee_high(&self, threshold: u8) -> bool {
+        self.fee_per_gram() > u64::from(threshold)
+    }
+
+    /// The payments to external recipients, in the order they were added
+    pub fn payments(&self) -> impl Iterator<Item = &Payment> {
+        self.payments.iter().flatten()
+    }
+
+    /// The value and script of every output that is neither verified change nor a payment, in the order they were added
+    pub fn unverified_outputs(&self) -> impl Iterator<Item = (u64, &ScriptSummary)> {
+        self.outputs
+            .iter()
+            .flatten()
+            .filter(|output| !output.verified)
+            .filter_map(|output| Some((output.value, output.script.as_ref()?)))
+    }
+
+    /// The script offset, after checking it against the sum of the public keys supplied by the host
+    /// - An offset of a single term would be the raw key of that input or output, so at least one script key and one
+    ///   sender offset key must have been added. A zero offset, of keys that cancel out, is rejected too.
+    pub fn script_offset(&self) -> Result<&RistrettoSecretKey, Error> {
+        if self.inputs[0].is_none() || self.outputs[0].is_none() {
+            return Err(Error::EmptyTransaction);
+        }
+        if self.script_offset == RistrettoSecretKey::default() {
+            return Err(Error::ZeroKey);
+        }
+        verify_public_key(&self.script_offset, &self.script_offset_public_key)?;
+        Ok(&self.script_offset)
+    }
+
+    /// Check that the transaction has inputs and outputs, whose metadata is complete, conserves value and that
+    /// `Σin - Σout - fee.H` is the claimed excess, before the kernel is signed
+    pub fn verify_excess(&self, claimed_excess: &RistrettoPublicKey) -> Result<(), Error> {
+        self.check_not_approved()?;
+        self.check_output_metadata()?;
+        if self.inputs[0].is_none() || self.outputs[0].is_none() {
+            return Err(Error::EmptyTransaction);
+        }
+        let spent = self
+            .output_value
+            .checked_add(self.fee)
+            .ok_or(Error::TransactionNotBalanced)?;
+        if self.input_value != spent {
+            return Err(Error::TransactionNotBalanced);
+        }
+        let fee_commitment =
+            PedersenCommitmentFactory::default().commit_value(&RistrettoSecretKey::default(), self.fee);
+        if &self.commitment_sum - fee_commitment.as_public_key() != *claimed_excess {
+            return Err(Error::TransactionNotBalanced);
+        }
+        // Masks that cancel out would leave the kernel to be signed by the zero key, a signature anyone can make
+        if self
+            .excess
+            .iter()
+            .all(|excess| *excess == RistrettoSecretKey::default())
+        {
+            return Err(Error::ZeroKey);
+        }
+        Ok(())
+    }
+
+    /// Record that the user approved the transaction with the kernel excess `excess`, which releases the attestations
+    /// of its inputs and outputs and freezes it
+    pub fn approve(&mut self, excess: &RistrettoPublicKey) {
+        self.approved_excess = Some(excess.clone());
+    }
+
+    /// Attest the script of the input at `position`, in the order the inputs were added, with its script key
+    /// - Returns `Error::TransactionNotApproved` before the user approved the transaction, and `Error::UnknownInput` if
+    ///   there is no input at `position`.
+    pub fn attest_input_script(&self, position: usize) -> Result<SchnorrSignature, Error> {
+        let excess = self.approved_excess.as_ref().ok_or(Error::TransactionNotApproved)?;
+        let input = self
+            .inputs
+            .get(position)
+            .and_then(Option::as_ref)
+            .ok_or(Error::UnknownInput)?;
+        sign_challenge(&input.script_key, |public_nonce, public_key| {
+            DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("script_attestation")
+                .chain(&public_nonce.as_bytes())
+                .chain(&public_key.as_bytes())
+                .chain(&input.commitment.as_bytes())
+                .chain(&excess.as_bytes())
+                .finalize()
+        })
+    }
+
+    /// Attest the metadata of the output at `position`, in the order the outputs were added, with its sender offset key
+    /// - Returns `Error::TransactionNotApproved` before the user approved the transaction, and
+    ///   `Error::MetadataNotBound` if there is no output at `position`.
+    pub fn attest_output_metadata(&self, position: usize) -> Result<SchnorrSignature, Error> {
+        if self.approved_excess.is_none() {
+            return Err(Error::TransactionNotApproved);
+        }
+        let output = self
+            .outputs
+            .get(position)
+            .and_then(Option::as_ref)
+            .ok_or(Error::MetadataNotBound)?;
+        let features_hash = output
+            .features_hash
+            .unwrap_or_else(|| metadata_field_hash("output_features", &STANDARD_OUTPUT_FEATURES));
+        let covenant_hash = output
+            .covenant_hash
+            .unwrap_or_else(|| metadata_field_hash("output_covenant", &EMPTY_COVENANT));
+        let metadata_hash = DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("output_metadata")
+            .chain(&output.commitment.as_bytes())
+            .chain(&output.script_hash.unwrap_or_default())
+            .chain(&features_hash)
+            .chain(&covenant_hash)
+            .finalize();
+        sign_challenge(&output.sender_offset_key, |public_nonce, public_key| {
+            DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("metadata_attestation")
+                .chain(&public_nonce.as_bytes())
+                .chain(&public_key.as_bytes())
+                .chain(&metadata_hash)
+                .finalize()
+        })
+    }
+
+    /// Sign the kernel for the excess `excess`, which must have been checked with `verify_excess`
+    pub fn sign_kernel(&self, excess: &RistrettoPublicKey) -> Result<KernelSignature, Error> {
+        let degree = self.extension_degree.size();
+        let factory = PedersenCommitmentFactory::new_with_extension_degree(self.extension_degree);
+        let mut nonces: [RistrettoSecretKey; MAX_EXTENSION_DEGREE] = Default::default();
+        for nonce in nonces.iter_mut().take(degree) {
+            *nonce = RistrettoSecretKey::random();
+        }
+        let public_nonce = factory
+            .commit_value_extended(&nonces[..degree], 0)?
+            .as_public_key()
+            .clone();
+
+        let challenge = RistrettoSecretKey::from_bytes(&self.kernel_challenge(&public_nonce, excess))?;
+        let mut signatures: [RistrettoSecretKey; MAX_EXTENSION_DEGREE] = Default::default();
+        for ((signature, nonce), excess) in signatures.iter_mut().zip(&nonces).zip(&self.excess).take(degree) {
+            *signature = nonce + &(&challenge * excess);
+        }
+
+        // As for every signature, one that a fault made invalid must never leave the device: `Σ s_j.G_j = R + e.X`
+        let lhs = factory.commit_value_extended(&signatures[..degree], 0)?;
+        if *lhs.as_public_key() != &public_nonce + &(excess * &challenge) {
+            return Err(Error::SignatureVerificationFailed);
+        }
+        Ok(KernelSignature {
+            public_nonce,
+            signatures,
+            extension_degree: self.extension_degree,
+        })
+    }
+
+    // Check that the transaction can still change, which it cannot once approved
+    fn check_not_approved(&self) -> Result<(), Error> {
+        if self.approved_excess.is_some() {
+            return Err(Error::TransactionApproved);
+        }
+        Ok(())
+    }
+
+    // The output added last, which metadata is bound to while the transaction can still change
+    fn last_output(&mut self) -> Result<&mut OutputMetadata, Error> {
+        self.check_not_approved()?;
+        self.outputs.iter_mut().flatten().last().ok_or(Error::MetadataNotBound)
+    }
+
+    // Check that the output added last has its script, before another output is added or the kernel is signed
+    fn check_output_metadata(&self) -> Result<(), Error> {
+        match self.outputs.iter().flatten().last() {
+            Some(output) if output.script_hash.is_none() => Err(Error::MetadataNotBound),
+            _ => Ok(()),
+        }
+    }
+
+    // Check that `commitment` opens to `value` with `masks`, raising the extension degree of the transaction to theirs
+    fn verify_opening(
+        &mut self,
+        commitment: &PedersenCommitment,
+        value: u64,
+        masks: &[RistrettoSecretKey],
+    ) -> Result<(), Error> {
+        let extension_degree = ExtensionDegree::try_from_size(masks.len())?;
+        let expected = PedersenCommitmentFactory::new_with_extension_degree(extension_degree)
+            .commit_value_extended(masks, value)?;
+        if expected != *commitment {
+            return Err(Error::CommitmentMismatch);
+        }
+        if extension_degree.size() > self.extension_degree.size() {
+            self.extension_degree = extension_degree;
+        }
+        Ok(())
+    }
+
+    /// The kernel signature challenge for the public nonce `public_nonce` and the excess `excess`
+    pub fn kernel_challenge(&self, public_nonce: &RistrettoPublicKey, excess: &RistrettoPublicKey) -> [u8; 32] {
+        DomainSeparatedConsensusHasher::<TransactionHashDomain>::new("kernel_signature")
+            .chain(&public_nonce.as_bytes())
+            .chain(&excess.as_bytes())
+            .chain(&KERNEL_VERSION)
+            .chain(&self.fee)
+            .chain(&self.lock_height)
+            .chain(&KERNEL_FEATURES)
+            .chain(&NO_BURN_COMMITMENT)
+            .finalize()
+    }
+}
+
+/// The fee threshold that follows `threshold` in `HIGH_FEE_THRESHOLDS`, wrapping around at the end
+pub fn next_high_fee_threshold(threshold: u8) -> u8 {
+    let index = HIGH_FEE_THRESHOLDS.iter().position(|t| *t == threshold).unwrap_or(0);
+    HIGH_FEE_THRESHOLDS[(index + 1) % HIGH_FEE_THRESHOLDS.len()]
+}
+
+// The size of `value` as a LEB128 varint
+fn varint_size(value: u64) -> u64 {
+    u64::from((64 - value.leading_zeros()).max(1) + 6) / 7
+}
+
+// Bind the hash of the metadata `bytes` under `label` to the field `hash` of an output, which must not be bound yet
+fn bind_metadata(hash: &mut Option<[u8; 32]>, label: &'static str, bytes: &[u8]) -> Result<(), Error> {
+    if hash.is_some() {
+        return Err(Error::MetadataAlreadyBound);
+    }
+    *hash = Some(metadata_field_hash(label, bytes));
+    Ok(())
+}
+
+// The hash of the script, features or covenant `bytes` of an output, under `label`
+fn metadata_field_hash(label: &'static str, bytes: &[u8]) -> [u8; 32] {
+    DomainSeparatedConsensusHasher::<TransactionHashDomain>::new(label)
+        .chain(&bytes)
+        .finalize()
+}
+
+// Sign with `secret_key` and a random nonce on the challenge that `challenge` computes from the public nonce and public
+// key
+fn sign_challenge(
+    secret_key: &RistrettoSecretKey,
+    challenge: impl FnOnce(&RistrettoPublicKey, &RistrettoPublicKey) -> [u8; 32],
+) -> Result<SchnorrSignature, Error> {
+    let public_key = RistrettoPublicKey::from_secret_key(secret_key);
+    let nonce = RistrettoSecretKey::random();
+    let public_nonce = RistrettoPublicKey::from_secret_key(&nonce);
+    let challenge = challenge(&public_nonce, &public_key);
+    let signature = SchnorrSignature::sign_raw(secret_key, nonce, &challenge)?;
+    // As for the kernel, a signature that a fault made invalid must never leave the device
+    if !signature.verify_challenge(&public_key, &challenge) {
+        return Err(Error::SignatureVerificationFailed);
+    }
+    Ok(signature)
+}
+
+// Check that `public_key` is the public key of `secret_key`
+fn verify_public_key(secret_key: &RistrettoSecretKey, public_key: &RistrettoPublicKey) -> Result<(), Error> {
+    if RistrettoPublicKey::from_secret_key(secret_key) == *public_key {
+        Ok(())
+    } else {
+        Err(Error::KeyMismatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nanos_sdk::{assert_eq_err as assert_eq, testing::TestType};
+    use testmacro::test_item as test;
+
+    use super::*;
+
+    const VALUE: u64 = 1000;
+
+    fn secret_key(byte: u8) -> RistrettoSecretKey {
+        RistrettoSecretKey::from_bytes(&[byte; 32]).unwrap()
+    }
+
+    // `PushPubKey(public_key)`
+    fn pay_to_key(public_key: &RistrettoPublicKey) -> [u8; 33] {
+        let mut script = [OP_PUSH_PUBKEY; 33];
+        script[1..].copy_from_slice(public_key.as_bytes());
+        script
+    }
+
+    // Add an output of `value` with the mask `[mask; 32]` and the script `script`
+    fn add_output(transaction: &mut TransactionState, value: u64, mask: u8, script: &[u8]) -> bool {
+        let mask = secret_key(mask);
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, value);
+        transaction
+            .add_output(&commitment, value, core::slice::from_ref(&mask))
+            .is_ok() &&
+            transaction.add_script(script).is_ok()
+    }
+
+    #[test]
+    fn weights_match_tari_core() {
+        // The weights are those of `TransactionWeight::v1().calculate`, with each output's features, script, covenant,
+        // encrypted data (80) and minimum value promise (8) rounded up to a multiple of 16 bytes
+        assert_eq!(TransactionState::new(0, 0, 0).weight(), 10);
+
+        // An input, change with the script `PushPubKey(K)`, which takes 16 + 34 + 1 + 88 = 139 bytes, rounded to 144,
+        // and a one-sided payment with the script `PushPubKey(R) Drop PushPubKey(K_s)`, which takes 16 + 68 + 1 + 88 =
+        // 173 bytes, rounded to 176
+        let mut transaction = TransactionState::new(0, 0, 0);
+        let mask = secret_key(1);
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, 2 * VALUE);
+        let script_key = secret_key(2);
+        let script_public_key = RistrettoPublicKey::from_secret_key(&script_key);
+        let added = transaction.add_input(
+            &commitment,
+            2 * VALUE,
+            core::slice::from_ref(&mask),
+            &script_key,
+            &script_public_key,
+        );
+        assert_eq!(added.is_ok(), true);
+        let mask = secret_key(3);
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, VALUE);
+        let script = pay_to_key(&script_public_key);
+        let added = transaction.add_change(
+            &commitment,
+            VALUE,
+            core::slice::from_ref(&mask),
+            &script_public_key,
+            &script,
+        );
+        assert_eq!(added.is_ok(), true);
+        let mut stealth_script = [OP_PUSH_PUBKEY; 67];
+        stealth_script[1..33].copy_from_slice(script_public_key.as_bytes());
+        stealth_script[33] = 0x70;
+        stealth_script[35..].copy_from_slice(script_public_key.as_bytes());
+        assert_eq!(add_output(&mut transaction, VALUE, 4, &stealth_script), true);
+        assert_eq!(transaction.weight(), 10 + 8 + 2 * 53 + 144 / 16 + 176 / 16);
+
+        // Two outputs time-locked with `CheckHeightVerify(h) PushPubKey(K)` take 16 + 43 + 1 + 88 = 148 bytes each,
+        // which round up to 10 grams per output rather than 19 grams for both
+        let mut time_locked = [OP_PUSH_PUBKEY; 42];
+        time_locked[0] = 0x66;
+        time_locked[1..9].copy_from_slice(&1000u64.to_le_bytes());
+        time_locked[10..].copy_from_slice(script_public_key.as_bytes());
+        let mut transaction = TransactionState::new(0, 0, 0);
+        assert_eq!(add_output(&mut transaction, VALUE, 3, &time_locked), true);
+        assert_eq!(add_output(&mut transaction, VALUE, 4, &time_locked), true);
+        assert_eq!(transaction.weight(), 10 + 2 * 53 + 2 * 10);
+
+        // Long scripts count in full, here 16 + 202 + 1 + 88 = 307 bytes, as do features and covenants once bound
+        let mut transaction = TransactionState::new(0, 0, 0);
+        assert_eq!(add_output(&mut transaction, VALUE, 3, &[0x73; 200]), true);
+        assert_eq!(transaction.weight(), 10 + 53 + 20);
+        assert_eq!(transaction.add_features(&[0; 40]).is_ok(), true);
+        assert_eq!(transaction.add_covenant(&[0; 2]).is_ok(), true);
+        assert_eq!(transaction.weight(), 10 + 53 + 21);
+    }
+
+    #[test]
+    fn change_round_trip() {
+        let mask = secret_key(1);
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, VALUE);
+        let script_key = secret_key(2);
+        let script_public_key = RistrettoPublicKey::from_secret_key(&script_key);
+        let masks = core::slice::from_ref(&mask);
+
+        let mut transaction = TransactionState::new(0, 0, 0);
+        let script = pay_to_key(&script_public_key);
+        assert_eq!(
+            transaction
+                .add_change(&commitment, VALUE, masks, &script_public_key, &script)
+                .is_ok(),
+            true
+        );
+        assert_eq!(transaction.unverified_outputs().count(), 0);
+
+        // The change is spent with the script key its script pushes
+        let mut transaction = TransactionState::new(0, 0, 0);
+        assert_eq!(
+            transaction
+                .add_input(&commitment, VALUE, masks, &script_key, &script_public_key)
+                .is_ok(),
+            true
+        );
+    }
+
+    #[test]
+    fn change_script_must_be_canonical() {
+        let mask = secret_key(1);
+        let commitment = PedersenCommitmentFactory::default().commit_value(&mask, VALUE);
+        let script_public_key = RistrettoPublicKey::from_secret_key(&secret_key(2));
+        let masks = core::slice::from_ref(&mask);
+        let script = pay_to_key(&script_public_key);
+        let change = |script: &[u8]| {
+            TransactionState::new(0, 0, 0)
+                .add_change(&commitment, VALUE, masks, &script_public_key, script)
+                .is_err()
+        };
+
+        // `PushOne PushPubKey(K)`, `PushPubKey(K) Nop` and `Nop PushPubKey(K)` all leave `K` on top of the stack
+        let mut prefixed = [OP_PUSH_PUBKEY; 34];
+        prefixed[0] = 0x7c;
+        prefixed[2..].copy_from_slice(script_public_key.as_bytes());
+        assert_eq!(change(&prefixed), true);
+        prefixed[0] = 0x73;
+        assert_eq!(change(&prefixed), true);
+        let mut suffixed = [0x73; 34];
+        suffixed[..33].copy_from_slice(&script);
+        assert_eq!(change(&suffixed), true);
+
+        // Nor may it pay to another key, or be cut short
+        let other_script = pay_to_key(&RistrettoPublicKey::from_secret_key(&secret_key(3)));
+        assert_eq!(change(&other_script), true);
+        assert_eq!(change(&script[..32]), true);
+        assert_eq!(change(&[]), true);
+        assert_eq!(change(&script), false);
+    }
+}